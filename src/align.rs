@@ -0,0 +1,421 @@
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use crate::analysis::{self, PitchParams};
+
+/// Time between feature frames, for both the spectral features and pitch.
+pub const HOP: Duration = Duration::from_millis(10);
+const FRAME: Duration = Duration::from_millis(25);
+const MEL_BANDS: usize = 26;
+/// Cepstral coefficients kept, not counting c0 which is dropped so loudness doesn't matter.
+const COEFFS: usize = 12;
+
+/// Mean feature distance at which the score has dropped to about 37.
+const SCORE_SCALE: f32 = 20.0;
+/// How much faster or slower than the rest of the take a stretch has to be to count as
+/// a timing divergence.
+const TIMING_RATIO: f32 = 1.6;
+/// Frames either side used to measure the local speed.
+const TIMING_WINDOW: usize = 5;
+const PITCH_SEMITONES: f32 = 3.0;
+/// Divergences shorter than this are ignored.
+const MIN_DIVERGENCE: usize = 6;
+
+/// Per-frame features of a recording, one frame every [`HOP`].
+#[derive(Clone, Debug)]
+pub struct Features {
+    /// Mean-normalised MFCCs.
+    pub mfcc: Vec<Vec<f32>>,
+    pub f0: Vec<Option<f32>>,
+}
+
+impl Features {
+    /// From mono `samples`.
+    pub fn new(samples: &[f32], sample_rate: u32) -> Self {
+        let params = PitchParams {
+            hop: HOP,
+            ..Default::default()
+        };
+        Self {
+            mfcc: mfcc(samples, sample_rate),
+            f0: analysis::pitch_track(samples, sample_rate, &params),
+        }
+    }
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+/// Triangular filters evenly spaced on the mel scale, as weights per FFT bin.
+fn mel_filters(bins: usize, fft_len: usize, sample_rate: u32) -> Vec<Vec<f32>> {
+    let top = hz_to_mel(sample_rate as f32 / 2.0);
+    let edges: Vec<f32> = (0..MEL_BANDS + 2)
+        .map(|i| mel_to_hz(top * i as f32 / (MEL_BANDS + 1) as f32))
+        .map(|hz| hz * fft_len as f32 / sample_rate as f32)
+        .collect();
+    edges
+        .windows(3)
+        .map(|w| {
+            (0..bins)
+                .map(|bin| {
+                    let bin = bin as f32;
+                    if bin <= w[0] || bin >= w[2] {
+                        0.0
+                    } else if bin <= w[1] {
+                        (bin - w[0]) / (w[1] - w[0])
+                    } else {
+                        (w[2] - bin) / (w[2] - w[1])
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Mel-frequency cepstral coefficients of mono `samples`, one frame every [`HOP`]
+/// centred like [`analysis::pitch_track`]'s, with the mean of each coefficient removed.
+pub fn mfcc(samples: &[f32], sample_rate: u32) -> Vec<Vec<f32>> {
+    let (samples, rate) = analysis::downsample(samples, sample_rate, analysis::ANALYSIS_RATE);
+    let hop = ((HOP.as_secs_f64() * rate as f64).round() as usize).max(1);
+    let frame_len = (FRAME.as_secs_f64() * rate as f64).round() as usize;
+    let fft_len = frame_len.next_power_of_two();
+    let bins = fft_len / 2 + 1;
+    let fft = FftPlanner::new().plan_fft_forward(fft_len);
+    let window: Vec<f32> = (0..frame_len)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / frame_len as f32).cos())
+        .collect();
+    let filters = mel_filters(bins, fft_len, rate);
+
+    let mut buffer = vec![Complex::new(0.0, 0.0); fft_len];
+    let mut frames: Vec<Vec<f32>> = (0..samples.len())
+        .step_by(hop)
+        .map(|centre| {
+            buffer.fill(Complex::new(0.0, 0.0));
+            let start = centre as isize - frame_len as isize / 2;
+            for (i, w) in window.iter().enumerate() {
+                let ind = start + i as isize;
+                if ind >= 0 && (ind as usize) < samples.len() {
+                    buffer[i].re = samples[ind as usize] * w;
+                }
+            }
+            fft.process(&mut buffer);
+            let log_mel: Vec<f32> = filters
+                .iter()
+                .map(|f| {
+                    let energy: f32 = f.iter().zip(&buffer).map(|(w, c)| w * c.norm_sqr()).sum();
+                    (energy + 1e-10).ln()
+                })
+                .collect();
+            (1..=COEFFS)
+                .map(|n| {
+                    log_mel
+                        .iter()
+                        .enumerate()
+                        .map(|(b, e)| {
+                            e * (PI * n as f32 * (b as f32 + 0.5) / MEL_BANDS as f32).cos()
+                        })
+                        .sum()
+                })
+                .collect()
+        })
+        .collect();
+
+    // cepstral mean normalisation takes out most of the microphone's colouring
+    if !frames.is_empty() {
+        for n in 0..COEFFS {
+            let mean = frames.iter().map(|f| f[n]).sum::<f32>() / frames.len() as f32;
+            for frame in frames.iter_mut() {
+                frame[n] -= mean;
+            }
+        }
+    }
+    frames
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Dynamic time warping between two feature sequences. Returns the mean distance along
+/// the cheapest path, and the path as `(a, b)` index pairs from start to end.
+pub fn dtw(a: &[Vec<f32>], b: &[Vec<f32>]) -> (f32, Vec<(usize, usize)>) {
+    let (n, m) = (a.len(), b.len());
+    if n == 0 || m == 0 {
+        return (f32::INFINITY, vec![]);
+    }
+    let mut cost = vec![f32::INFINITY; n * m];
+    let at = |cost: &[f32], i: usize, j: usize| cost[i * m + j];
+    for i in 0..n {
+        for j in 0..m {
+            let best = match (i, j) {
+                (0, 0) => 0.0,
+                (0, _) => at(&cost, 0, j - 1),
+                (_, 0) => at(&cost, i - 1, 0),
+                _ => at(&cost, i - 1, j - 1)
+                    .min(at(&cost, i - 1, j))
+                    .min(at(&cost, i, j - 1)),
+            };
+            cost[i * m + j] = best + distance(&a[i], &b[j]);
+        }
+    }
+
+    let mut path = vec![(n - 1, m - 1)];
+    let (mut i, mut j) = (n - 1, m - 1);
+    while (i, j) != (0, 0) {
+        (i, j) = match (i, j) {
+            (0, _) => (0, j - 1),
+            (_, 0) => (i - 1, 0),
+            _ => [(i - 1, j - 1), (i - 1, j), (i, j - 1)]
+                .into_iter()
+                .min_by(|x, y| at(&cost, x.0, x.1).total_cmp(&at(&cost, y.0, y.1)))
+                .unwrap(),
+        };
+        path.push((i, j));
+    }
+    path.reverse();
+    (cost[n * m - 1] / path.len() as f32, path)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DivergenceKind {
+    Timing,
+    Pitch,
+}
+
+/// A stretch of the model where the take differed noticeably.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    pub kind: DivergenceKind,
+    pub start: Duration,
+    pub end: Duration,
+    /// How far off it was: the take's relative speed for timing (above 1 is slower),
+    /// semitones for pitch (above 0 is higher).
+    pub amount: f32,
+}
+
+/// How a take lines up with the model.
+#[derive(Clone, Debug)]
+pub struct Alignment {
+    /// Matched `(model frame, take frame)` pairs, in order.
+    pub path: Vec<(usize, usize)>,
+    /// 0 to 100, higher is closer to the model.
+    pub score: f32,
+    pub divergences: Vec<Divergence>,
+}
+
+impl Alignment {
+    /// The take frame matched to each model frame, averaged where several were.
+    pub fn take_frames(&self) -> Vec<f32> {
+        let len = self.path.last().map_or(0, |(i, _)| i + 1);
+        let mut sums = vec![(0.0, 0); len];
+        for (i, j) in self.path.iter() {
+            sums[*i].0 += *j as f32;
+            sums[*i].1 += 1;
+        }
+        sums.into_iter().map(|(sum, n)| sum / n as f32).collect()
+    }
+}
+
+/// Aligns `take` to `model` and finds where their timing and pitch differ.
+pub fn align(model: &Features, take: &Features) -> Alignment {
+    let (distance, path) = dtw(&model.mfcc, &take.mfcc);
+    let mut alignment = Alignment {
+        score: 100.0 * (-distance / SCORE_SCALE).exp(),
+        path,
+        divergences: vec![],
+    };
+    let matched = alignment.take_frames();
+    if matched.len() < 2 {
+        return alignment;
+    }
+
+    // local speed against the take's typical speed, so a take that's slower throughout
+    // isn't flagged everywhere
+    let slopes: Vec<f32> = (0..matched.len())
+        .map(|i| {
+            let lo = i.saturating_sub(TIMING_WINDOW);
+            let hi = (i + TIMING_WINDOW).min(matched.len() - 1);
+            (matched[hi] - matched[lo]) / (hi - lo) as f32
+        })
+        .collect();
+    let typical = median(slopes.clone()).unwrap_or(1.0).max(f32::EPSILON);
+    let timing = slopes.iter().map(|s| {
+        let ratio = s / typical;
+        (!(1.0 / TIMING_RATIO..=TIMING_RATIO).contains(&ratio)).then_some(ratio)
+    });
+    alignment
+        .divergences
+        .extend(stretches(timing, DivergenceKind::Timing));
+
+    // same idea for pitch: a speaker with a higher voice throughout isn't diverging
+    let diffs: Vec<Option<f32>> = matched
+        .iter()
+        .enumerate()
+        .map(|(i, j)| {
+            let model = model.f0.get(i).copied().flatten()?;
+            let take = take.f0.get(j.round() as usize).copied().flatten()?;
+            Some(12.0 * (take / model).log2())
+        })
+        .collect();
+    if let Some(offset) = median(diffs.iter().flatten().copied().collect()) {
+        let pitch = diffs.iter().map(|d| {
+            let d = (*d)? - offset;
+            (d.abs() > PITCH_SEMITONES).then_some(d)
+        });
+        alignment
+            .divergences
+            .extend(stretches(pitch, DivergenceKind::Pitch));
+    }
+    alignment
+}
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f32::total_cmp);
+    Some(values[values.len() / 2])
+}
+
+/// Runs of flagged frames, at least [`MIN_DIVERGENCE`] long, with their mean amount.
+fn stretches(flags: impl Iterator<Item = Option<f32>>, kind: DivergenceKind) -> Vec<Divergence> {
+    let mut runs: Vec<(usize, usize, f32)> = vec![];
+    let mut last = None;
+    for (i, amount) in flags.enumerate() {
+        let Some(amount) = amount else {
+            continue;
+        };
+        match runs.last_mut() {
+            Some((_, end, sum)) if last == Some(i - 1) => {
+                *end = i + 1;
+                *sum += amount;
+            }
+            _ => runs.push((i, i + 1, amount)),
+        }
+        last = Some(i);
+    }
+    runs.into_iter()
+        .filter(|(start, end, _)| end - start >= MIN_DIVERGENCE)
+        .map(|(start, end, sum)| Divergence {
+            kind,
+            start: HOP * start as u32,
+            end: HOP * end as u32,
+            amount: sum / (end - start) as f32,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    /// A buzzy tone with a few harmonics, closer to a voice than a sine.
+    fn tone(hz: f32, secs: f32) -> Vec<f32> {
+        (0..(secs * RATE as f32) as usize)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                (1..=5)
+                    .map(|h| (2.0 * PI * hz * h as f32 * t).sin() / h as f32)
+                    .sum::<f32>()
+                    * 0.3
+            })
+            .collect()
+    }
+
+    fn notes(notes: &[(f32, f32)]) -> Vec<f32> {
+        notes
+            .iter()
+            .flat_map(|(hz, secs)| tone(*hz, *secs))
+            .collect()
+    }
+
+    #[test]
+    fn dtw_of_identical_sequences_is_diagonal() {
+        let a: Vec<Vec<f32>> = (0..5).map(|i| vec![i as f32]).collect();
+        let (distance, path) = dtw(&a, &a);
+        assert_eq!(distance, 0.0);
+        assert_eq!(path, (0..5).map(|i| (i, i)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dtw_follows_a_stretched_sequence() {
+        let a: Vec<Vec<f32>> = (0..5).map(|i| vec![i as f32]).collect();
+        let b: Vec<Vec<f32>> = (0..10).map(|i| vec![(i / 2) as f32]).collect();
+        let (distance, path) = dtw(&a, &b);
+        assert_eq!(distance, 0.0);
+        for (i, j) in path {
+            assert_eq!(i, j / 2);
+        }
+    }
+
+    #[test]
+    fn dtw_of_empty_input() {
+        let (distance, path) = dtw(&[], &[vec![1.0]]);
+        assert!(distance.is_infinite());
+        assert!(path.is_empty());
+    }
+
+    #[test]
+    fn identical_take_scores_full_marks() {
+        let model = Features::new(&notes(&[(200.0, 0.3), (400.0, 0.3)]), RATE);
+        let alignment = align(&model, &model);
+        assert!((alignment.score - 100.0).abs() < 1e-3);
+        assert!(alignment.divergences.is_empty());
+    }
+
+    #[test]
+    fn different_take_scores_lower() {
+        let model = Features::new(&notes(&[(200.0, 0.3), (400.0, 0.3), (800.0, 0.3)]), RATE);
+        let similar = Features::new(&notes(&[(200.0, 0.4), (400.0, 0.4), (800.0, 0.4)]), RATE);
+        let other = Features::new(&notes(&[(800.0, 0.3), (150.0, 0.3), (500.0, 0.3)]), RATE);
+        let similar = align(&model, &similar).score;
+        let other = align(&model, &other).score;
+        assert!(similar > other, "{similar} vs {other}");
+    }
+
+    #[test]
+    fn finds_a_dragged_out_note() {
+        let model = Features::new(&notes(&[(200.0, 0.3), (400.0, 0.3), (800.0, 0.3)]), RATE);
+        let take = Features::new(&notes(&[(200.0, 0.3), (400.0, 0.9), (800.0, 0.3)]), RATE);
+        let alignment = align(&model, &take);
+        let timing: Vec<&Divergence> = alignment
+            .divergences
+            .iter()
+            .filter(|d| d.kind == DivergenceKind::Timing)
+            .collect();
+        assert_eq!(timing.len(), 1, "{timing:?}");
+        assert!(timing[0].start >= Duration::from_millis(250));
+        assert!(timing[0].end <= Duration::from_millis(650));
+        assert!(timing[0].amount > 2.0);
+    }
+
+    #[test]
+    fn finds_an_off_pitch_note() {
+        let shifted = 300.0 * 2f32.powf(5.0 / 12.0);
+        let model = Features::new(&notes(&[(200.0, 0.3), (300.0, 0.3), (250.0, 0.3)]), RATE);
+        let take = Features::new(&notes(&[(200.0, 0.3), (shifted, 0.3), (250.0, 0.3)]), RATE);
+        let alignment = align(&model, &take);
+        let pitch: Vec<&Divergence> = alignment
+            .divergences
+            .iter()
+            .filter(|d| d.kind == DivergenceKind::Pitch)
+            .collect();
+        assert_eq!(pitch.len(), 1, "{pitch:?}");
+        assert!(pitch[0].start >= Duration::from_millis(250));
+        assert!(pitch[0].end <= Duration::from_millis(650));
+        assert!((pitch[0].amount - 5.0).abs() < 0.5, "{pitch:?}");
+    }
+}
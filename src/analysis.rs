@@ -0,0 +1,342 @@
+use std::time::Duration;
+
+/// Tuning for [`detect_segments`].
+#[derive(Clone, Debug)]
+pub struct SilenceParams {
+    /// Frames quieter than this, relative to the loudest frame, count as silence.
+    pub threshold_db: f32,
+    /// Pauses shorter than this are treated as part of the phrase.
+    pub min_silence: Duration,
+    /// Anything shorter than this is dropped as noise.
+    pub min_segment: Duration,
+    pub frame: Duration,
+}
+
+impl Default for SilenceParams {
+    fn default() -> Self {
+        Self {
+            threshold_db: -35.0,
+            min_silence: Duration::from_millis(300),
+            min_segment: Duration::from_millis(200),
+            frame: Duration::from_millis(10),
+        }
+    }
+}
+
+/// RMS of consecutive, non-overlapping frames of `frame_len` samples.
+pub fn frame_rms(samples: &[f32], frame_len: usize) -> Vec<f32> {
+    samples
+        .chunks(frame_len.max(1))
+        .map(|frame| (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt())
+        .collect()
+}
+
+/// The middle of the quietest `frame_len`-sample frame of `samples`, as a sample index.
+/// Of frames equally quiet, the one nearest the middle of `samples` wins.
+pub fn quietest(samples: &[f32], frame_len: usize) -> Option<usize> {
+    let frame_len = frame_len.max(1);
+    let rms = frame_rms(samples, frame_len);
+    let middle = samples.len() / 2;
+    let centre = |i: usize| i * frame_len + frame_len.min(samples.len() - i * frame_len) / 2;
+    (0..rms.len())
+        .min_by(|a, b| {
+            rms[*a].total_cmp(&rms[*b]).then(
+                centre(*a)
+                    .abs_diff(middle)
+                    .cmp(&centre(*b).abs_diff(middle)),
+            )
+        })
+        .map(centre)
+}
+
+/// Finds the spoken phrases in mono `samples`, as `(start, end)` pairs separated by
+/// pauses of at least `min_silence`.
+pub fn detect_segments(
+    samples: &[f32],
+    sample_rate: u32,
+    params: &SilenceParams,
+) -> Vec<(Duration, Duration)> {
+    let frame_len = (params.frame.as_secs_f64() * sample_rate as f64).round() as usize;
+    let frame_len = frame_len.max(1);
+    let rms = frame_rms(samples, frame_len);
+    let peak = rms.iter().cloned().fold(0.0, f32::max);
+    if peak <= 0.0 {
+        return vec![];
+    }
+    let threshold = peak * 10f32.powf(params.threshold_db / 20.0);
+    let frame_secs = frame_len as f64 / sample_rate as f64;
+    let min_silence = (params.min_silence.as_secs_f64() / frame_secs).ceil() as usize;
+
+    // runs of loud frames, with short pauses bridged
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for (ind, _) in rms.iter().enumerate().filter(|(_, r)| **r > threshold) {
+        match runs.last_mut() {
+            Some((_, end)) if ind - *end < min_silence.max(1) => *end = ind + 1,
+            _ => runs.push((ind, ind + 1)),
+        }
+    }
+
+    let to_time = |frame: usize| Duration::from_secs_f64(frame as f64 * frame_secs);
+    runs.into_iter()
+        .map(|(start, end)| (to_time(start), to_time(end)))
+        .filter(|(start, end)| *end - *start >= params.min_segment)
+        .collect()
+}
+
+/// How much to lengthen the pauses between phrases, see [`expanded_pauses`].
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Expansion {
+    /// Make each pause this many times as long.
+    Stretch(f32),
+    /// Add this much silence per second of the phrase before the pause, time to echo it.
+    Echo(f32),
+}
+
+/// Where to add silence between `phrases` (as from [`detect_segments`]) and how much.
+/// It goes in the middle of each pause, well away from the speech either side.
+pub fn expanded_pauses(
+    phrases: &[(Duration, Duration)],
+    expansion: Expansion,
+) -> Vec<(Duration, Duration)> {
+    phrases
+        .windows(2)
+        .filter_map(|pair| {
+            let ((start, end), (next, _)) = (pair[0], pair[1]);
+            let pause = next.checked_sub(end)?;
+            let extra = match expansion {
+                Expansion::Stretch(factor) => pause.mul_f64((factor - 1.0).max(0.0) as f64),
+                Expansion::Echo(ratio) => (end - start).mul_f64(ratio.max(0.0) as f64),
+            };
+            (!extra.is_zero()).then_some((end + pause / 2, extra))
+        })
+        .collect()
+}
+
+/// Tuning for [`pitch_track`].
+#[derive(Clone, Debug)]
+pub struct PitchParams {
+    pub min_hz: f32,
+    pub max_hz: f32,
+    /// How aperiodic a frame can be and still count as voiced. Lower is stricter.
+    pub threshold: f32,
+    /// Frames quieter than this, relative to the loudest frame, are unvoiced.
+    pub silence_db: f32,
+    pub hop: Duration,
+    /// The audio is downsampled to at most this rate first. Lower is faster and coarser.
+    pub max_rate: u32,
+}
+
+impl Default for PitchParams {
+    fn default() -> Self {
+        Self {
+            min_hz: 60.0,
+            max_hz: 500.0,
+            threshold: 0.15,
+            silence_db: -40.0,
+            hop: Duration::from_millis(10),
+            max_rate: ANALYSIS_RATE,
+        }
+    }
+}
+
+/// Pitch and spectral features are computed at no more than this rate. Speech doesn't
+/// need more, and it keeps the per-frame work down.
+pub const ANALYSIS_RATE: u32 = 16_000;
+
+/// Averages `samples` down by a whole factor to at most `max_rate`. Returns the new rate.
+pub fn downsample(samples: &[f32], sample_rate: u32, max_rate: u32) -> (Vec<f32>, u32) {
+    let factor = (sample_rate / max_rate).max(1) as usize;
+    let samples = samples
+        .chunks(factor)
+        .map(|c| c.iter().sum::<f32>() / c.len() as f32)
+        .collect();
+    (samples, sample_rate / factor as u32)
+}
+
+/// Fundamental frequency of `frame` using YIN, or `None` if it isn't clearly periodic.
+/// The frame has to cover at least two periods of `min_hz`.
+pub fn yin(frame: &[f32], sample_rate: u32, params: &PitchParams) -> Option<f32> {
+    let rate = sample_rate as f32;
+    let tau_min = ((rate / params.max_hz) as usize).max(2);
+    let tau_max = (rate / params.min_hz).ceil() as usize;
+    if frame.len() < 2 * tau_max || tau_min >= tau_max {
+        return None;
+    }
+    let width = frame.len() - tau_max;
+
+    // cumulative mean normalised difference
+    let mut cmnd = vec![1.0; tau_max + 1];
+    let mut running = 0.0;
+    for tau in 1..=tau_max {
+        let diff: f32 = frame[..width]
+            .iter()
+            .zip(&frame[tau..tau + width])
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum();
+        running += diff;
+        if running > 0.0 {
+            cmnd[tau] = diff * tau as f32 / running;
+        }
+    }
+
+    let mut tau = (tau_min..tau_max).find(|tau| cmnd[*tau] < params.threshold)?;
+    while tau + 1 < tau_max && cmnd[tau + 1] < cmnd[tau] {
+        tau += 1;
+    }
+    // parabolic interpolation around the dip
+    let (a, b, c) = (cmnd[tau - 1], cmnd[tau], cmnd[tau + 1]);
+    let denom = a - 2.0 * b + c;
+    let offset = if denom.abs() > f32::EPSILON {
+        0.5 * (a - c) / denom
+    } else {
+        0.0
+    };
+    Some(rate / (tau as f32 + offset))
+}
+
+/// F0 of mono `samples` every `params.hop`, `None` where unvoiced. Entry `i` is centred
+/// on `i * hop`.
+pub fn pitch_track(samples: &[f32], sample_rate: u32, params: &PitchParams) -> Vec<Option<f32>> {
+    let (samples, rate) = downsample(samples, sample_rate, params.max_rate);
+
+    let hop = ((params.hop.as_secs_f64() * rate as f64).round() as usize).max(1);
+    let frame_len = 2 * (rate as f32 / params.min_hz).ceil() as usize;
+    let rms = |frame: &[f32]| {
+        frame_rms(frame, frame.len())
+            .first()
+            .copied()
+            .unwrap_or(0.0)
+    };
+    let peak = frame_rms(&samples, hop).into_iter().fold(0.0, f32::max);
+    let threshold = peak * 10f32.powf(params.silence_db / 20.0);
+
+    (0..samples.len())
+        .step_by(hop)
+        .map(|centre| {
+            let start = centre.saturating_sub(frame_len / 2);
+            let frame = samples.get(start..start + frame_len)?;
+            if rms(frame) <= threshold {
+                return None;
+            }
+            yin(frame, rate, params)
+        })
+        .collect()
+}
+
+/// Each F0 in semitones from the contour's median, so speakers with different voices
+/// can be compared by intonation alone.
+pub fn relative_semitones(f0: &[Option<f32>]) -> Vec<Option<f32>> {
+    let mut voiced: Vec<f32> = f0.iter().flatten().copied().collect();
+    if voiced.is_empty() {
+        return vec![None; f0.len()];
+    }
+    voiced.sort_by(f32::total_cmp);
+    let median = voiced[voiced.len() / 2];
+    f0.iter()
+        .map(|f| f.map(|f| 12.0 * (f / median).log2()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    fn sine(hz: f32, secs: f32, rate: u32) -> Vec<f32> {
+        (0..(secs * rate as f32) as usize)
+            .map(|i| (TAU * hz * i as f32 / rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn yin_finds_sine_frequency() {
+        for hz in [80.0, 147.0, 220.0, 410.0] {
+            let found = yin(&sine(hz, 0.05, 16_000), 16_000, &PitchParams::default()).unwrap();
+            assert!((found - hz).abs() < hz * 0.01, "{hz} Hz tracked as {found}");
+        }
+    }
+
+    #[test]
+    fn pitch_track_follows_a_step() {
+        let mut samples = sine(150.0, 0.5, 44_100);
+        samples.extend(sine(300.0, 0.5, 44_100));
+        let track = pitch_track(&samples, 44_100, &PitchParams::default());
+        assert_eq!(track.len(), 100);
+        let near = |f: Option<f32>, hz: f32| f.is_some_and(|f| (f - hz).abs() < hz * 0.02);
+        assert!(track[10..40].iter().all(|f| near(*f, 150.0)));
+        assert!(track[60..90].iter().all(|f| near(*f, 300.0)));
+    }
+
+    #[test]
+    fn silence_and_noise_are_unvoiced() {
+        let params = PitchParams::default();
+        let mut samples = vec![0.0; 8_000];
+        samples.extend(sine(200.0, 0.5, 16_000));
+        let track = pitch_track(&samples, 16_000, &params);
+        assert!(track[..45].iter().all(Option::is_none));
+
+        // deterministic white-ish noise
+        let mut x = 1u32;
+        let noise: Vec<f32> = (0..800)
+            .map(|_| {
+                x = x.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (x >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+        assert_eq!(yin(&noise, 16_000, &params), None);
+    }
+
+    #[test]
+    fn detects_phrases_between_pauses() {
+        let rate = 1_000;
+        let mut samples = sine(200.0, 0.5, rate);
+        samples.extend(vec![0.0; 400]);
+        samples.extend(sine(200.0, 0.3, rate));
+        // too short a pause to split on
+        samples.extend(vec![0.0; 100]);
+        samples.extend(sine(200.0, 0.3, rate));
+        samples.extend(vec![0.001; 300]);
+        // too short to be a phrase
+        samples.extend(sine(200.0, 0.05, rate));
+        let ms = Duration::from_millis;
+        let phrases = detect_segments(&samples, rate, &SilenceParams::default());
+        assert_eq!(phrases, vec![(ms(0), ms(500)), (ms(900), ms(1600))]);
+        assert!(detect_segments(&[0.0; 500], rate, &SilenceParams::default()).is_empty());
+    }
+
+    #[test]
+    fn quietest_finds_the_pause() {
+        let mut samples = sine(200.0, 0.2, 1_000);
+        samples.extend(vec![0.0; 30]);
+        samples.extend(sine(200.0, 0.2, 1_000));
+        let at = quietest(&samples, 10).unwrap();
+        assert!((200..230).contains(&at), "{at}");
+        // all silent: stay in the middle
+        assert_eq!(quietest(&[0.0; 100], 10), Some(45));
+        assert_eq!(quietest(&[], 10), None);
+    }
+
+    #[test]
+    fn pauses_expand_in_the_middle() {
+        let ms = Duration::from_millis;
+        let phrases = [
+            (ms(0), ms(1000)),
+            (ms(1200), ms(1700)),
+            (ms(2100), ms(3000)),
+        ];
+        let stretched = expanded_pauses(&phrases, Expansion::Stretch(2.0));
+        assert_eq!(stretched, vec![(ms(1100), ms(200)), (ms(1900), ms(400))]);
+        let echoed = expanded_pauses(&phrases, Expansion::Echo(0.5));
+        assert_eq!(echoed, vec![(ms(1100), ms(500)), (ms(1900), ms(250))]);
+        assert!(expanded_pauses(&phrases, Expansion::Stretch(1.0)).is_empty());
+        assert!(expanded_pauses(&phrases[..1], Expansion::Echo(1.0)).is_empty());
+    }
+
+    #[test]
+    fn semitones_are_relative_to_median() {
+        let st = relative_semitones(&[Some(100.0), None, Some(200.0), Some(200.0)]);
+        assert_eq!(st[1], None);
+        assert!((st[0].unwrap() + 12.0).abs() < 1e-4);
+        assert!(st[2].unwrap().abs() < 1e-4);
+    }
+}
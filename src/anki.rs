@@ -0,0 +1,94 @@
+use anyhow::{bail, Context, Result};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::app::audio::Pcm;
+use crate::app::filters::Filters;
+use crate::export::{self, ClipSettings};
+use crate::marks::Segment;
+
+/// Writes `<stem>.txt`, a tab separated file Anki can import as Basic notes, and
+/// `<stem>_media/` with one clip per card. The clips have to be copied into Anki's
+/// `collection.media` folder by hand, Anki doesn't pick them up on import.
+///
+/// Front: the segment audio. Back: the transcript and translation. Segments without a
+/// label, like the stretch before the first mark, make no card.
+pub fn export_deck(
+    pcm: &Pcm,
+    segments: &[Segment],
+    dir: &Path,
+    stem: &str,
+    settings: &ClipSettings,
+    filters: &Filters,
+) -> Result<PathBuf> {
+    let labelled: Vec<_> = segments
+        .iter()
+        .enumerate()
+        .filter(|(_, segment)| !segment.label.trim().is_empty())
+        .collect();
+    if labelled.is_empty() {
+        bail!("no labelled segments to make cards from, give the marks a label first");
+    }
+    let media = dir.join(format!("{stem}_media"));
+    std::fs::create_dir_all(&media)
+        .with_context(|| format!("couldn't create {}", media.display()))?;
+
+    let mut notes = String::new();
+    _ = writeln!(notes, "#separator:tab");
+    _ = writeln!(notes, "#html:true");
+    _ = writeln!(notes, "#notetype:Basic");
+    _ = writeln!(notes, "#deck:{}", header(stem));
+    for (ind, segment) in labelled {
+        // media names are global in an Anki collection, so keep them tied to the file
+        let name = format!("{stem}_{:03}.{}", ind + 1, settings.format.extension());
+        export::export_clip(pcm, segment, &media.join(&name), settings, filters)?;
+
+        let mut back = field(&segment.label);
+        if !segment.translation.trim().is_empty() {
+            _ = write!(back, "<br>{}", field(&segment.translation));
+        }
+        _ = writeln!(notes, "[sound:{name}]\t{back}");
+    }
+
+    let path = dir.join(format!("{stem}.txt"));
+    std::fs::write(&path, notes).with_context(|| format!("couldn't write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Text for a `#key:value` header line, which Anki takes as it is: only what would
+/// break the line is taken out.
+fn header(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\r' | '\n'))
+        .collect()
+}
+
+/// Escapes text for an HTML field, keeping it on one line.
+fn field(text: &str) -> String {
+    text.trim()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\t', " ")
+        .replace('\r', "")
+        .replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_escaped_onto_one_line() {
+        assert_eq!(field("  a < b && c > d "), "a &lt; b &amp;&amp; c &gt; d");
+        assert_eq!(field("uno\tdos\r\ntres"), "uno dos<br>tres");
+        assert_eq!(field("<br>"), "&lt;br&gt;");
+    }
+
+    #[test]
+    fn deck_names_are_kept_as_they_are() {
+        assert_eq!(header("A&B <1>"), "A&B <1>");
+        assert_eq!(header("uno\tdos\r\n"), "unodos");
+    }
+}
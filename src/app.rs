@@ -1,9 +1,33 @@
-use egui::Key;
+use self::actions::Action;
 
 use self::audio::AudioPlayer;
+use crate::anki;
+use crate::cli::LaunchOptions;
+use crate::edit;
+use crate::export::{self, ClipFormat, ClipSettings};
+use crate::marks::{self, Mark, Span};
+use crate::project::Project;
+use crate::render::{self, RenderSettings};
+use crate::review::ReviewStore;
+use crate::stats::{PracticeStats, Tracker};
 
-mod audio;
+mod actions;
+pub(crate) mod audio;
+mod capture;
+mod compare;
+pub(crate) mod filters;
+mod input;
+mod overrides;
+mod pauses;
+mod pitch;
+mod ramp;
+mod recorder;
+mod review;
+mod shortcuts;
 mod slider;
+mod spectrogram;
+mod stats;
+mod transport;
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -17,7 +41,144 @@ pub struct TemplateApp {
 
     picked_path: Option<String>,
 
-    marks: Vec<f32>,
+    marks: Vec<Mark>,
+
+    spans: Vec<Span>,
+
+    render_settings: RenderSettings,
+
+    clip_settings: ClipSettings,
+
+    /// Segments being learned with spaced repetition, across all files.
+    reviews: ReviewStore,
+
+    stats: PracticeStats,
+
+    /// Draw the file's pitch contour above the timeline.
+    show_pitch: bool,
+
+    /// Show the spectrogram panel under the timeline.
+    show_spectrogram: bool,
+
+    keymap: shortcuts::Keymap,
+
+    buttons: input::ButtonMap,
+
+    jumps: actions::JumpSettings,
+
+    mark_settings: actions::MarkSettings,
+
+    padding: actions::Padding,
+
+    speed_ramp: ramp::SpeedRamp,
+
+    segment_defaults: overrides::SegmentDefaults,
+
+    pause_settings: pauses::PauseSettings,
+
+    filter_settings: filters::FilterSettings,
+
+    /// What's left of the command line to apply once its file has loaded.
+    #[serde(skip)]
+    launch: Option<LaunchOptions>,
+
+    /// Why the last file couldn't be opened.
+    #[serde(skip)]
+    load_status: Option<String>,
+
+    #[serde(skip)]
+    render_open: bool,
+
+    #[serde(skip)]
+    render_status: Option<String>,
+
+    /// The practice track being rendered in the background, and where it's going.
+    #[serde(skip)]
+    rendering: Option<(
+        std::path::PathBuf,
+        std::sync::mpsc::Receiver<anyhow::Result<()>>,
+    )>,
+
+    #[serde(skip)]
+    export_open: bool,
+
+    #[serde(skip)]
+    export_status: Option<String>,
+
+    /// The clips being written in the background. Says what was written once done.
+    #[serde(skip)]
+    exporting: Option<std::sync::mpsc::Receiver<anyhow::Result<String>>>,
+
+    #[serde(skip)]
+    review_open: bool,
+
+    #[serde(skip)]
+    review: review::ReviewSession,
+
+    #[serde(skip)]
+    stats_open: bool,
+
+    #[serde(skip)]
+    stats_status: Option<String>,
+
+    #[serde(skip)]
+    tracker: Tracker,
+
+    #[serde(skip)]
+    compare_open: bool,
+
+    #[serde(skip)]
+    compare: compare::Comparison,
+
+    #[serde(skip)]
+    pitch: pitch::PitchCache,
+
+    #[serde(skip)]
+    spectrogram: spectrogram::Spectrogram,
+
+    #[serde(skip)]
+    shortcuts_open: bool,
+
+    /// Start of the span being captured, and what's held down for it.
+    #[serde(skip)]
+    capture: Option<(f32, capture::Holder)>,
+
+    #[serde(skip)]
+    shortcut_editor: shortcuts::ShortcutEditor,
+
+    #[serde(skip)]
+    inputs: input::Inputs,
+
+    /// Where the playhead was last frame.
+    #[serde(skip)]
+    played_to: f32,
+
+    /// The last mark played through, where "replay last phrase" goes back to.
+    #[serde(skip)]
+    last_crossed: Option<f32>,
+
+    /// Undo and redo for the marks, saved along with them.
+    history: edit::History,
+
+    /// What's being typed into or adjusted, until it goes into the history.
+    #[serde(skip)]
+    editing: Option<actions::Editing>,
+
+    #[serde(skip)]
+    ramp_progress: Option<ramp::RampProgress>,
+
+    #[serde(skip)]
+    repeating: Option<overrides::Repeating>,
+
+    #[serde(skip)]
+    pause_finder: pauses::PauseFinder,
+
+    #[serde(skip)]
+    noise_status: Option<String>,
+
+    /// How far the "Shift marks" button moves them, in seconds.
+    #[serde(skip)]
+    shift_secs: f32,
 
     #[serde(skip)]
     audio: AudioPlayer,
@@ -31,6 +192,52 @@ impl Default for TemplateApp {
             picked_path: None,
             cur_pos: 0.0,
             marks: vec![],
+            spans: vec![],
+            capture: None,
+            launch: None,
+            load_status: None,
+            render_settings: Default::default(),
+            render_open: false,
+            render_status: None,
+            rendering: None,
+            clip_settings: Default::default(),
+            export_open: false,
+            export_status: None,
+            exporting: None,
+            reviews: Default::default(),
+            review_open: false,
+            review: Default::default(),
+            stats: Default::default(),
+            stats_open: false,
+            stats_status: None,
+            tracker: Default::default(),
+            compare_open: false,
+            compare: Default::default(),
+            show_pitch: false,
+            pitch: Default::default(),
+            show_spectrogram: false,
+            spectrogram: Default::default(),
+            keymap: Default::default(),
+            shortcuts_open: false,
+            shortcut_editor: Default::default(),
+            buttons: Default::default(),
+            inputs: Default::default(),
+            jumps: Default::default(),
+            mark_settings: Default::default(),
+            padding: Default::default(),
+            speed_ramp: Default::default(),
+            ramp_progress: None,
+            segment_defaults: Default::default(),
+            repeating: None,
+            pause_settings: Default::default(),
+            pause_finder: Default::default(),
+            filter_settings: Default::default(),
+            noise_status: None,
+            played_to: 0.0,
+            last_crossed: None,
+            history: Default::default(),
+            editing: None,
+            shift_secs: 0.0,
             audio,
         }
     }
@@ -46,27 +253,500 @@ impl TemplateApp {
         // Note that you must enable the `persistence` feature for this to work.
         if let Some(storage) = cc.storage {
             let mut r: Self = eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+            r.audio.set_fade_in(r.padding.fade);
             if let Some(path) = r.picked_path.as_ref() {
                 // try to load previous file
-                _ = r.audio.load(path.as_str());
+                r.audio.load(path.as_str());
             }
             return r;
         }
 
         Default::default()
     }
+
+    /// Like [`TemplateApp::new`], but then opens whatever was asked for on the command line.
+    /// Explicit options win over the project, which wins over the restored state.
+    pub fn with_options(cc: &eframe::CreationContext<'_>, mut opts: LaunchOptions) -> Self {
+        let mut r = Self::new(cc);
+
+        if let Some(project) = opts.project.take() {
+            r.open_project(project);
+        }
+        if let Some(file) = opts.file.take() {
+            let path = file.display().to_string();
+            r.audio.load(path.as_str());
+            r.picked_path = Some(path);
+            r.marks.clear();
+            r.spans.clear();
+            r.history = Default::default();
+            r.editing = None;
+            r.stop_repeating();
+            r.cur_pos = 0.0;
+        }
+        if let Some(speed) = opts.speed {
+            r.playback_speed = speed;
+        }
+        // marks and the start position need the file's length
+        if r.audio.is_loading() {
+            r.launch = Some(opts);
+        } else if opts.marks.is_some() || opts.start.is_some() {
+            r.load_status =
+                Some("--marks and --start need an audio file, give one or a project".to_owned());
+        }
+        r
+    }
+
+    /// Picks up the file once it's loaded, finishing off the command line with it.
+    fn follow_load(&mut self, ctx: &egui::Context) {
+        if self.audio.is_loading() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+        match self.audio.finish_load() {
+            Some(Ok(())) => {
+                self.load_status = None;
+                if let Some(opts) = self.launch.take() {
+                    self.finish_launch(opts);
+                }
+            }
+            Some(Err(e)) => {
+                self.load_status = Some(format!("{e:#}"));
+                self.launch = None;
+            }
+            None => {}
+        }
+    }
+
+    fn finish_launch(&mut self, opts: LaunchOptions) {
+        let total = self.audio.total_time().map(|t| t.as_secs_f32());
+        if let (Some(cues), Some(total)) = (opts.marks, total) {
+            let mut imported: Vec<Mark> = cues
+                .into_iter()
+                .map(|c| {
+                    let (label, translation) = c.transcript();
+                    Mark {
+                        label,
+                        translation,
+                        ..Mark::new((c.start.as_secs_f32() / total).clamp(0.0, 1.0))
+                    }
+                })
+                .collect();
+            imported.sort_by(|a, b| a.pos.partial_cmp(&b.pos).unwrap());
+            imported.dedup_by(|a, b| a.pos == b.pos);
+            self.edit(edit::replace(&self.marks, imported));
+        }
+        if let (Some(start), Some(total)) = (opts.start, total) {
+            self.cur_pos = (start.as_secs_f32() / total).clamp(0.0, 1.0);
+            self.audio.scrub_to(self.cur_pos);
+        }
+    }
+
+    fn open_project(&mut self, project: Project) {
+        self.marks = project.marks;
+        self.spans = project.spans;
+        self.history = project.history;
+        self.editing = None;
+        self.stop_repeating();
+        self.playback_speed = project.playback_speed;
+        self.cur_pos = 0.0;
+        self.picked_path = project.audio_path;
+        if let Some(path) = self.picked_path.as_ref() {
+            self.audio.load(path.as_str());
+        }
+    }
+
+    /// Writes the current file and marks out as a practice track on a worker thread, see
+    /// [`render::practice_track`]. [`TemplateApp::follow_render`] reports how it went.
+    fn render_practice_track(&mut self, path: std::path::PathBuf) {
+        let (Some(pcm), Some(total)) = (self.audio.pcm(), self.audio.total_time()) else {
+            self.render_status = Some("couldn't render: no audio file is loaded".to_owned());
+            return;
+        };
+        let segments = marks::segments(&self.marks, total);
+        let settings = self.render_settings.clone();
+        let filters = self.audio.filters.fixed();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let out = path.clone();
+        std::thread::spawn(move || {
+            let track = render::practice_track(&pcm, &segments, &settings, &filters);
+            _ = tx.send(track.write_wav(out));
+        });
+        self.render_status = None;
+        self.rendering = Some((path, rx));
+    }
+
+    fn follow_render(&mut self, ctx: &egui::Context) {
+        let Some((path, rx)) = self.rendering.as_ref() else {
+            return;
+        };
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                return;
+            }
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("rendering stopped"))
+            }
+        };
+        self.render_status = Some(match result {
+            Ok(_) => format!("wrote {}", path.display()),
+            Err(e) => format!("couldn't render: {e:#}"),
+        });
+        self.rendering = None;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn render_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.render_open;
+        egui::Window::new("Render practice track")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let settings = &mut self.render_settings;
+                ui.add(
+                    egui::Slider::new(&mut settings.speed, 0.5..=3.0)
+                        .text("Speed")
+                        .logarithmic(true),
+                );
+                ui.add(egui::Slider::new(&mut settings.repeat, 1..=10).text("Repeats"));
+                let mut gap = settings.gap.as_secs_f32();
+                if ui
+                    .add(egui::Slider::new(&mut gap, 0.0..=5.0).text("Gap (s)"))
+                    .changed()
+                {
+                    settings.gap = std::time::Duration::from_secs_f32(gap);
+                }
+                ui.add(
+                    egui::Slider::new(&mut settings.gap_scale, 0.0..=2.0)
+                        .text("Gap per segment length"),
+                );
+                ui.checkbox(&mut settings.beep, "Beep before each segment");
+
+                let idle = self.rendering.is_none();
+                if ui.add_enabled(idle, egui::Button::new("Render…")).clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("wav", &["wav"])
+                        .save_file()
+                    {
+                        self.render_practice_track(path);
+                    }
+                }
+                if !idle {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("rendering…");
+                    });
+                }
+                if let Some(status) = self.render_status.as_ref() {
+                    ui.label(status);
+                }
+            });
+        self.render_open = open;
+    }
+
+    /// Asks where to save the segment starting at mark `ind`, then writes it.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_segment(&mut self, ind: usize) {
+        self.export_open = true;
+        if self.exporting.is_some() {
+            return;
+        }
+        let (Some(pcm), Some(total)) = (self.audio.pcm(), self.audio.total_time()) else {
+            return;
+        };
+        let Some(segment) = marks::segment_at(&self.marks, ind, total) else {
+            return;
+        };
+        // numbered as when exporting them all, which counts the stretch before the first mark
+        let number = marks::segments(&self.marks, total)
+            .iter()
+            .position(|s| s.start == segment.start)
+            .unwrap_or(ind);
+        let format = self.clip_settings.format;
+        let name = export::file_name(number, &segment, &self.file_stem(), format);
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name(&name)
+            .add_filter(format.extension(), &[format.extension()])
+            .save_file()
+        else {
+            return;
+        };
+        let settings = self.clip_settings.clone();
+        let filters = self.audio.filters.fixed();
+        self.start_export(move || {
+            export::export_clip(&pcm, &segment, &path, &settings, &filters)?;
+            Ok(format!("wrote {}", path.display()))
+        });
+    }
+
+    /// Writes every segment into `dir` on a worker thread, see [`export::export_clips`].
+    fn export_all_segments(&mut self, dir: std::path::PathBuf) {
+        let (Some(pcm), Some(total)) = (self.audio.pcm(), self.audio.total_time()) else {
+            self.export_status = Some("couldn't export: no audio file is loaded".to_owned());
+            return;
+        };
+        let segments = marks::segments(&self.marks, total);
+        let stem = self.file_stem();
+        let settings = self.clip_settings.clone();
+        let filters = self.audio.filters.fixed();
+        self.start_export(move || {
+            let paths = export::export_clips(&pcm, &segments, &dir, &stem, &settings, &filters)?;
+            Ok(format!("wrote {} clips to {}", paths.len(), dir.display()))
+        });
+    }
+
+    /// Runs an export on a worker thread, `job` saying what it wrote.
+    /// [`TemplateApp::follow_export`] shows how it went.
+    fn start_export(&mut self, job: impl FnOnce() -> anyhow::Result<String> + Send + 'static) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            _ = tx.send(job());
+        });
+        self.export_status = None;
+        self.exporting = Some(rx);
+    }
+
+    fn follow_export(&mut self, ctx: &egui::Context) {
+        let Some(rx) = self.exporting.as_ref() else {
+            return;
+        };
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                return;
+            }
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("exporting stopped"))
+            }
+        };
+        self.export_status = Some(match result {
+            Ok(wrote) => wrote,
+            Err(e) => format!("couldn't export: {e:#}"),
+        });
+        self.exporting = None;
+    }
+
+    /// Writes the segments as an Anki deck into `dir` on a worker thread, see
+    /// [`anki::export_deck`].
+    fn export_anki_deck(&mut self, dir: std::path::PathBuf) {
+        let (Some(pcm), Some(total)) = (self.audio.pcm(), self.audio.total_time()) else {
+            self.export_status = Some("couldn't export: no audio file is loaded".to_owned());
+            return;
+        };
+        let segments = marks::segments(&self.marks, total);
+        let stem = self.file_stem();
+        let settings = self.clip_settings.clone();
+        let filters = self.audio.filters.fixed();
+        self.start_export(move || {
+            let path = anki::export_deck(&pcm, &segments, &dir, &stem, &settings, &filters)?;
+            Ok(format!(
+                "wrote {}, import it with File > Import and copy the media folder's contents \
+                into Anki's collection.media",
+                path.display()
+            ))
+        });
+    }
+
+    fn file_stem(&self) -> String {
+        self.picked_path
+            .as_ref()
+            .map(|p| export::file_stem(std::path::Path::new(p)))
+            .unwrap_or_default()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.export_open;
+        egui::Window::new("Export segments")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let settings = &mut self.clip_settings;
+                let mut padding = settings.padding.as_secs_f32() * 1000.0;
+                if ui
+                    .add(egui::Slider::new(&mut padding, 0.0..=1000.0).text("Padding (ms)"))
+                    .changed()
+                {
+                    settings.padding = std::time::Duration::from_secs_f32(padding / 1000.0);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Format");
+                    for format in ClipFormat::ALL {
+                        ui.add_enabled_ui(format.available(), |ui| {
+                            ui.radio_value(&mut settings.format, format, format.extension())
+                                .on_disabled_hover_text("no encoder installed");
+                        });
+                    }
+                });
+
+                let idle = self.exporting.is_none();
+                if ui
+                    .add_enabled(idle, egui::Button::new("Export all segments…"))
+                    .clicked()
+                {
+                    if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                        self.export_all_segments(dir);
+                    }
+                }
+                if ui
+                    .add_enabled(idle, egui::Button::new("Export Anki deck…"))
+                    .clicked()
+                {
+                    if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                        self.export_anki_deck(dir);
+                    }
+                }
+                if !idle {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("exporting…");
+                    });
+                }
+                if let Some(status) = self.export_status.as_ref() {
+                    ui.label(status);
+                }
+            });
+        self.export_open = open;
+    }
+
+    fn project(&self) -> Project {
+        Project {
+            audio_path: self.picked_path.clone(),
+            marks: self.marks.clone(),
+            spans: self.spans.clone(),
+            history: self.history.clone(),
+            playback_speed: self.playback_speed,
+        }
+    }
 }
 
 impl eframe::App for TemplateApp {
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        #[cfg(not(target_arch = "wasm32"))] // no File->Quit on web pages!
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            // The top panel is often a good place for a menu bar:
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("File").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().pick_file() {
+                            self.picked_path = Some(path.display().to_string());
+                            self.audio.load(path.display().to_string().as_str());
+                            self.stop_repeating();
+                        }
+                    }
+                    if ui.button("Open Project").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("mochido project", &["ron"])
+                            .pick_file()
+                        {
+                            match Project::load(path) {
+                                Ok(project) => self.open_project(project),
+                                Err(e) => self.load_status = Some(format!("{e:#}")),
+                            }
+                        }
+                    }
+                    if ui.button("Save Project").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("mochido project", &["ron"])
+                            .save_file()
+                        {
+                            if let Err(e) = self.project().save(path) {
+                                self.load_status = Some(format!("{e:#}"));
+                            }
+                        }
+                    }
+                    if ui.button("Render Practice Track").clicked() {
+                        self.render_settings.speed = self.playback_speed;
+                        self.render_status = None;
+                        self.render_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Export Segments").clicked() {
+                        self.export_status = None;
+                        self.export_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Review Due Segments").clicked() {
+                        self.start_review(ui.input().time);
+                        ui.close_menu();
+                    }
+                    if ui.button("Compare With Model").clicked() {
+                        self.compare_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Statistics").clicked() {
+                        self.stats_status = None;
+                        self.stats_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Keyboard Shortcuts").clicked() {
+                        self.shortcuts_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Quit").clicked() {
+                        _frame.close();
+                    }
+                });
+                egui::warn_if_debug_build(ui);
+            });
+        });
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.render_window(ctx);
+        self.follow_render(ctx);
+        self.follow_export(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.export_window(ctx);
+        self.review_window(ctx);
+        self.stats_window(ctx);
+        self.compare_window(ctx);
+        self.track_practice(ctx);
+        self.spectrogram_panel(ctx);
+        self.shortcuts_window(ctx);
+        self.handle_shortcuts(ctx);
+        self.handle_buttons(ctx);
+        self.follow_load(ctx);
+        let crossed = self.follow_playhead();
+        self.follow_segment(crossed);
+        self.follow_pauses(ctx);
+        self.audio.filters.apply(&self.filter_settings);
+        self.follow_ramp();
+
+        let looping = self.audio.loop_region().is_some();
+        let capturing = self.capture.is_some();
+        let mut action = None;
+        let mut capture_held = false;
+        let mut shift_request = None;
+        let mut noise_request = false;
+        let mut delete_request = None;
+        let mut delete_span = None;
+        let mut editing = None;
+
+        let mut export_request = None;
+        let mut learn_request = None;
+
         let Self {
             playback_speed,
             picked_path,
             cur_pos,
             marks,
+            spans,
             audio,
+            show_pitch,
+            pitch,
+            show_spectrogram,
+            shift_secs,
+            padding,
+            speed_ramp,
+            ramp_progress,
+            segment_defaults,
+            pause_settings,
+            pause_finder,
+            filter_settings,
+            noise_status,
+            load_status,
+            ..
         } = self;
 
         if audio.is_playing() {
@@ -80,28 +760,6 @@ impl eframe::App for TemplateApp {
         // Tip: a good default choice is to just keep the `CentralPanel`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
 
-        #[cfg(not(target_arch = "wasm32"))] // no File->Quit on web pages!
-        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            // The top panel is often a good place for a menu bar:
-            egui::menu::bar(ui, |ui| {
-                ui.menu_button("File", |ui| {
-                    if ui.button("File").clicked() {
-                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                            *picked_path = Some(path.display().to_string());
-                            match audio.load(path.display().to_string().as_str()) {
-                                Ok(_) => {}
-                                Err(_) => {}
-                            }
-                        }
-                    }
-                    if ui.button("Quit").clicked() {
-                        _frame.close();
-                    }
-                });
-                egui::warn_if_debug_build(ui);
-            });
-        });
-
         egui::SidePanel::left("side_panel").show(ctx, |ui| {
             ui.heading("Side Panel");
 
@@ -113,6 +771,15 @@ impl eframe::App for TemplateApp {
                     });
                 }
             }
+            if audio.is_loading() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.weak("loading…");
+                });
+            }
+            if let Some(status) = load_status.as_ref() {
+                ui.label(status);
+            }
 
             if let Some(source) = audio.source.as_mut() {
                 ui.add(
@@ -146,52 +813,79 @@ impl eframe::App for TemplateApp {
                     },
                 );
             }
+
+            ui.collapsing("Segment padding", |ui| {
+                let ms = |d: &mut std::time::Duration, range, ui: &mut egui::Ui| {
+                    let mut ms = d.as_millis() as u64;
+                    let changed = ui
+                        .add(
+                            egui::DragValue::new(&mut ms)
+                                .clamp_range(range)
+                                .suffix(" ms"),
+                        )
+                        .changed();
+                    *d = std::time::Duration::from_millis(ms);
+                    changed
+                };
+                egui::Grid::new("padding_grid").show(ui, |ui| {
+                    ui.label("Before");
+                    ms(&mut padding.pre, 0..=1000, ui);
+                    ui.end_row();
+                    ui.label("After");
+                    ms(&mut padding.post, 0..=1000, ui);
+                    ui.end_row();
+                    ui.label("Fade");
+                    if ms(&mut padding.fade, 1..=200, ui) {
+                        audio.set_fade_in(padding.fade);
+                    }
+                    ui.end_row();
+                });
+            });
+            ui.collapsing("Segment playback", |ui| {
+                overrides::defaults_ui(ui, segment_defaults);
+            });
+            ui.collapsing("Pause expansion", |ui| {
+                if pauses::pauses_ui(ui, pause_settings, pause_finder) {
+                    // the point is hearing the speech at its own speed
+                    *playback_speed = 1.0;
+                }
+            });
+            ui.collapsing("Clean up", |ui| {
+                noise_request = filters::filters_ui(ui, filter_settings, noise_status.as_deref());
+            });
+            ui.collapsing("Speed ramp", |ui| {
+                ramp::ramp_ui(ui, speed_ramp, *ramp_progress);
+            });
+            ui.checkbox(show_pitch, "Pitch overlay");
+            ui.checkbox(show_spectrogram, "Spectrogram");
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            if ctx.input().key_pressed(Key::Space) {
-                audio.toggle_play();
-            }
-            if ctx.input().key_pressed(Key::ArrowLeft) {
-                if marks.is_empty() {
-                    *cur_pos = 0.0;
-                }
-                // if we're playing we'll have a small offset to jump past something if it's
-                // "too close"
-                let pos = *cur_pos + if audio.is_playing() { -0.05 } else { 0.0 };
-                if let Some(val) = marks.iter().rev().find(|i| *i < &pos) {
-                    *cur_pos = *val;
-                } else {
-                    *cur_pos = 0.0;
-                }
-                audio.scrub_to(*cur_pos);
-            }
-            if ctx.input().key_pressed(Key::ArrowRight) {
-                if marks.is_empty() {
-                    // TODO: or whatever max should be
-                    *cur_pos = 1.0;
-                }
-                if let Some(val) = marks.iter().find(|i| *i > cur_pos) {
-                    *cur_pos = *val;
-                } else {
-                    // TODO: or whatever max should be
-                    *cur_pos = 1.0;
-                }
-                audio.scrub_to(*cur_pos);
-            }
             // if ctx.input(|i| i.key_pressed(Key::Space)) {
             //     audio.toggle_play();
             // }
             // The central panel the region left after adding TopPanel's and SidePanel's
+            let total = audio.total_time();
+            let contour = match picked_path.as_ref() {
+                Some(path) if *show_pitch => {
+                    pitch.request(path, || audio.pcm());
+                    pitch.get(path)
+                }
+                _ => None,
+            };
+            if pitch.is_pending() {
+                ctx.request_repaint_after(std::time::Duration::from_millis(250));
+            }
+
             ui.style_mut().spacing.slider_width = ui.max_rect().width();
             ui.vertical_centered_justified(|ui| {
                 // let slider = egui::Slider::new(cur_pos, 0.0..=1.0).show_value(true);
                 // let slider = slider::Slider::new(cur_pos, 0.0..=1.0);
-                let slider = slider::Slider::from_get_set(
+                let mut slider = slider::Slider::from_get_set(
                     0.0..=1.0,
                     || {
                         // hmmm
-                        marks.to_vec()
+                        marks.iter().map(|m| m.pos).collect()
                     },
                     |v: Option<f64>| {
                         if let Some(v) = v {
@@ -201,6 +895,14 @@ impl eframe::App for TemplateApp {
                         eframe::emath::Numeric::to_f64(*cur_pos)
                     },
                 );
+                if let (Some(contour), Some(total)) = (contour, total) {
+                    slider = slider.overlay(slider::Overlay {
+                        values: &contour.heights,
+                        step: contour.hop.as_secs_f64() / total.as_secs_f64(),
+                        height: 48.0,
+                        color: ui.visuals().hyperlink_color,
+                    });
+                }
                 // ui.add(egui::Slider::new(cur_pos, 0.0..=1.0).show_value(true));
                 ui.add(slider);
             });
@@ -208,49 +910,68 @@ impl eframe::App for TemplateApp {
             ui.spacing_mut().item_spacing.y = 10.0;
             ui.horizontal_top(|ui| {
                 if ui.button("Mark").clicked() {
-                    if marks.contains(cur_pos) {
-                        return;
-                    }
-                    marks.push(*cur_pos);
-                    marks.sort_by(|a, b| a.partial_cmp(b).unwrap());
-                    // TODO: sort
+                    action = Some(Action::Mark);
                 }
                 if ui.button("Prev").clicked() {
-                    if marks.is_empty() {
-                        *cur_pos = 0.0;
-                    }
-                    // if we're playing we'll have a small offset to jump past something if it's
-                    // "too close"
-                    let pos = *cur_pos + if audio.is_playing() { -0.05 } else { 0.0 };
-                    if let Some(val) = marks.iter().rev().find(|i| *i < &pos) {
-                        *cur_pos = *val;
-                    } else {
-                        *cur_pos = 0.0;
-                    }
-                    audio.scrub_to(*cur_pos);
+                    action = Some(Action::PrevMark);
                 }
-                if audio.is_playing() {
-                    if ui.button("Pause").clicked() {
-                        audio.toggle_play();
-                    }
-                } else if ui.button("Play").clicked() {
-                    audio.toggle_play();
+                let play = if audio.is_playing() { "Pause" } else { "Play" };
+                if ui.button(play).clicked() {
+                    action = Some(Action::PlayPause);
                 }
                 if ui.button("Next").clicked() {
-                    if marks.is_empty() {
-                        // TODO: or whatever max should be
-                        *cur_pos = 1.0;
-                    }
-                    if let Some(val) = marks.iter().find(|i| *i > cur_pos) {
-                        *cur_pos = *val;
-                    } else {
-                        // TODO: or whatever max should be
-                        *cur_pos = 1.0;
+                    action = Some(Action::NextMark);
+                }
+                if ui.selectable_label(looping, "Loop").clicked() {
+                    action = Some(Action::LoopSegment);
+                }
+                let hold = if capturing {
+                    "Capturing…"
+                } else {
+                    "Hold to capture"
+                };
+                capture_held = ui
+                    .add(egui::Button::new(hold).sense(egui::Sense::drag()))
+                    .is_pointer_button_down_on();
+            });
+            ui.horizontal_top(|ui| {
+                let edits = [
+                    ("Split", Action::SplitSegment),
+                    ("Merge ←", Action::MergePrev),
+                    ("Merge →", Action::MergeNext),
+                    ("Trim start", Action::TrimStart),
+                    ("Trim end", Action::TrimEnd),
+                ];
+                for (text, edit) in edits {
+                    if ui.button(text).on_hover_text(edit.name()).clicked() {
+                        action = Some(edit);
                     }
-                    audio.scrub_to(*cur_pos);
+                }
+                ui.separator();
+                ui.add(egui::DragValue::new(shift_secs).speed(0.01).suffix("s"));
+                if ui.button("Shift marks").clicked() {
+                    shift_request = Some(*shift_secs);
+                }
+                ui.separator();
+                if ui.button("Undo").clicked() {
+                    action = Some(Action::Undo);
+                }
+                if ui.button("Redo").clicked() {
+                    action = Some(Action::Redo);
                 }
             });
 
+            if let (false, Some(total)) = (spans.is_empty(), total) {
+                let picked = capture::spans_ui(ui, spans, total);
+                if let Some(pos) = picked.jump {
+                    *cur_pos = pos;
+                    audio.scrub_to(pos);
+                }
+                delete_span = picked.delete;
+                editing = picked.editing;
+                ui.separator();
+            }
+
             if !marks.is_empty() {
                 // ui.spacing_mut().item_spacing.y = 20.0;
                 ui.with_layout(
@@ -261,14 +982,49 @@ impl eframe::App for TemplateApp {
                                 return;
                             }
                             ui.horizontal_top(|ui| {
-                                ui.label(format!("{}: {}", ind, marks.get(ind).expect("no")));
+                                let mark = marks.get_mut(ind).expect("no");
+                                let before = mark.clone();
+                                ui.label(format!("{}: {}", ind, mark.pos));
+                                let label = ui.add(
+                                    egui::TextEdit::singleline(&mut mark.label)
+                                        .hint_text("label")
+                                        .desired_width(160.0),
+                                );
+                                let translation = ui.add(
+                                    egui::TextEdit::singleline(&mut mark.translation)
+                                        .hint_text("translation")
+                                        .desired_width(160.0),
+                                );
+                                let settings = ui.menu_button("⚙", |ui| {
+                                    overrides::overrides_ui(
+                                        ui,
+                                        &mut mark.overrides,
+                                        *playback_speed,
+                                        segment_defaults,
+                                    );
+                                });
+                                settings
+                                    .response
+                                    .on_hover_text("Playback settings for this segment");
+                                if label.has_focus()
+                                    || translation.has_focus()
+                                    || settings.inner.is_some()
+                                {
+                                    editing = Some(actions::Editing::Mark(ind, before));
+                                }
+                                overrides::badges_ui(ui, &mark.overrides);
                                 if ui.button("Jump").clicked() {
-                                    *cur_pos = *marks.get(ind).expect("can't jump");
+                                    *cur_pos = marks.get(ind).expect("can't jump").pos;
                                     audio.scrub_to(*cur_pos);
                                 }
+                                if ui.button("Export").clicked() {
+                                    export_request = Some(ind);
+                                }
+                                if ui.button("Learn").clicked() {
+                                    learn_request = Some(ind);
+                                }
                                 if ui.button("Delete").clicked() {
-                                    marks.remove(ind);
-                                    ctx.request_repaint();
+                                    delete_request = Some(ind);
                                 }
                             });
                         }
@@ -277,6 +1033,31 @@ impl eframe::App for TemplateApp {
             }
         });
 
+        self.follow_editing(editing);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(ind) = export_request {
+            self.export_segment(ind);
+        }
+        if let Some(ind) = learn_request {
+            self.learn_segment(ind);
+        }
+        if let Some(action) = action {
+            self.perform(action);
+        }
+        if let Some(secs) = shift_request {
+            self.shift_marks(secs);
+        }
+        if noise_request {
+            self.pick_noise_sample();
+        }
+        if let Some(ind) = delete_request {
+            self.edit(edit::remove(&self.marks, ind));
+        }
+        if let Some(ind) = delete_span {
+            self.edit(edit::remove_span(&self.spans, ind));
+        }
+        self.hold_capture_pointer(capture_held);
+
         if false {
             egui::Window::new("Window").show(ctx, |ui| {
                 ui.label("Windows can be moved by dragging them.");
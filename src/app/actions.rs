@@ -0,0 +1,398 @@
+use std::time::Duration;
+
+use super::audio;
+use super::capture::Holder;
+use super::TemplateApp;
+use crate::analysis;
+use crate::edit::{self, Edit};
+use crate::marks::{self, Mark, Span};
+use crate::navigation;
+
+/// Step for the speed up/down actions.
+const SPEED_STEP: f32 = 0.1;
+/// Resolution of the search for a pause to snap marks to.
+const SNAP_FRAME: Duration = Duration::from_millis(10);
+/// A playhead move longer than this between frames is a seek, not playback.
+const MAX_PLAYED: Duration = Duration::from_secs(1);
+
+/// How far the rewind and nudge actions move the playhead, in file time so they mean
+/// the same thing at any speed.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct JumpSettings {
+    pub rewind: Duration,
+    pub nudge: Duration,
+}
+
+impl Default for JumpSettings {
+    fn default() -> Self {
+        Self {
+            rewind: Duration::from_secs(2),
+            nudge: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Where new marks go relative to the playhead, see [`TemplateApp::mark_position`].
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct MarkSettings {
+    /// How long it takes to press Mark after hearing where it should go.
+    pub reaction: Duration,
+    /// Move new marks to the quietest point within `snap_window` either side.
+    pub snap: bool,
+    pub snap_window: Duration,
+}
+
+impl Default for MarkSettings {
+    fn default() -> Self {
+        Self {
+            reaction: Duration::from_millis(200),
+            snap: false,
+            snap_window: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Extra audio either side of a segment when jumping to or looping it, so onsets aren't
+/// clipped. The jumps crossfade over `fade`, which is also what stops the padding
+/// clicking at either end of a loop.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Padding {
+    pub pre: Duration,
+    pub post: Duration,
+    pub fade: Duration,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Self {
+            pre: Duration::from_millis(150),
+            post: Duration::from_millis(250),
+            fade: audio::FADE_IN,
+        }
+    }
+}
+
+/// Something the user can do without the mouse. Shortcuts and the transport buttons
+/// all go through [`TemplateApp::perform`].
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub enum Action {
+    PlayPause,
+    Mark,
+    PrevMark,
+    NextMark,
+    LoopSegment,
+    SpeedUp,
+    SpeedDown,
+    ReplaySegment,
+    Record,
+    Rewind,
+    NudgeBack,
+    NudgeForward,
+    ReplayPhrase,
+    CaptureSegment,
+    SplitSegment,
+    MergePrev,
+    MergeNext,
+    TrimStart,
+    TrimEnd,
+    Undo,
+    Redo,
+}
+
+impl Action {
+    pub const ALL: [Action; 21] = [
+        Action::PlayPause,
+        Action::Mark,
+        Action::PrevMark,
+        Action::NextMark,
+        Action::LoopSegment,
+        Action::SpeedUp,
+        Action::SpeedDown,
+        Action::ReplaySegment,
+        Action::Record,
+        Action::Rewind,
+        Action::NudgeBack,
+        Action::NudgeForward,
+        Action::ReplayPhrase,
+        Action::CaptureSegment,
+        Action::SplitSegment,
+        Action::MergePrev,
+        Action::MergeNext,
+        Action::TrimStart,
+        Action::TrimEnd,
+        Action::Undo,
+        Action::Redo,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::PlayPause => "Play/pause",
+            Action::Mark => "Mark",
+            Action::PrevMark => "Previous mark",
+            Action::NextMark => "Next mark",
+            Action::LoopSegment => "Loop segment",
+            Action::SpeedUp => "Speed up",
+            Action::SpeedDown => "Slow down",
+            Action::ReplaySegment => "Replay segment",
+            Action::Record => "Record take",
+            Action::Rewind => "Go back",
+            Action::NudgeBack => "Nudge back",
+            Action::NudgeForward => "Nudge forward",
+            Action::ReplayPhrase => "Replay last phrase",
+            Action::CaptureSegment => "Capture segment (hold)",
+            Action::SplitSegment => "Split at playhead",
+            Action::MergePrev => "Merge with previous",
+            Action::MergeNext => "Merge with next",
+            Action::TrimStart => "Trim start to playhead",
+            Action::TrimEnd => "Trim end to playhead",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+        }
+    }
+}
+
+/// A mark or span whose text or settings are being changed in place, with what it
+/// was before. It goes into the history as one edit once the change is done.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Editing {
+    Mark(usize, Mark),
+    Span(usize, Span),
+}
+
+impl Editing {
+    fn same_target(&self, other: &Editing) -> bool {
+        match (self, other) {
+            (Editing::Mark(a, _), Editing::Mark(b, _)) => a == b,
+            (Editing::Span(a, _), Editing::Span(b, _)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl TemplateApp {
+    pub(super) fn perform(&mut self, action: Action) {
+        match action {
+            Action::PlayPause => self.audio.toggle_play(),
+            Action::Mark => self.edit(edit::split(&self.marks, self.mark_position())),
+            Action::PrevMark => {
+                if let Some(total) = self.audio.total_time() {
+                    let grace = if self.audio.is_playing() {
+                        navigation::GRACE
+                    } else {
+                        Duration::ZERO
+                    };
+                    let pos = navigation::prev(&self.marks, self.nav_pos(), total, grace);
+                    self.seek_padded(pos);
+                }
+            }
+            Action::NextMark => self.seek_padded(navigation::next(&self.marks, self.nav_pos())),
+            Action::LoopSegment => {
+                let region = match self.audio.loop_region() {
+                    Some(_) => None,
+                    None => self.current_segment().map(|seg| self.padded(seg)),
+                };
+                self.audio.set_loop(region);
+                self.start_ramp();
+            }
+            Action::SpeedUp => self.step_speed(SPEED_STEP),
+            Action::SpeedDown => self.step_speed(-SPEED_STEP),
+            Action::ReplaySegment => {
+                self.seek_padded(marks::segment_start(&self.marks, self.nav_pos()));
+                if !self.audio.is_playing() {
+                    self.audio.toggle_play();
+                }
+            }
+            Action::Record => {
+                self.compare_open = true;
+                self.toggle_recording();
+            }
+            Action::Rewind => self.seek_by(-self.jumps.rewind.as_secs_f32()),
+            Action::NudgeBack => self.seek_by(-self.jumps.nudge.as_secs_f32()),
+            Action::NudgeForward => self.seek_by(self.jumps.nudge.as_secs_f32()),
+            // held keys and buttons are handled where they're read, this is for
+            // anything that can only press
+            Action::CaptureSegment => match self.capture {
+                Some(_) => self.end_capture(),
+                None => self.begin_capture(Holder::Toggle),
+            },
+            Action::ReplayPhrase => {
+                let start = self
+                    .last_crossed
+                    .filter(|p| *p <= self.cur_pos)
+                    .unwrap_or_else(|| marks::segment_start(&self.marks, self.nav_pos()));
+                self.seek_padded(start);
+                if !self.audio.is_playing() {
+                    self.audio.toggle_play();
+                }
+            }
+            Action::SplitSegment => self.edit(edit::split(&self.marks, self.cur_pos)),
+            Action::MergePrev => self.edit(edit::merge_prev(&self.marks, self.cur_pos)),
+            Action::MergeNext => self.edit(edit::merge_next(&self.marks, self.cur_pos)),
+            Action::TrimStart => self.edit(edit::trim_start(&self.marks, self.cur_pos)),
+            Action::TrimEnd => self.edit(edit::trim_end(&self.marks, self.cur_pos)),
+            Action::Undo => {
+                self.finish_editing();
+                self.stop_repeating();
+                _ = self.history.undo(&mut self.marks, &mut self.spans);
+            }
+            Action::Redo => {
+                self.finish_editing();
+                self.stop_repeating();
+                _ = self.history.redo(&mut self.marks, &mut self.spans);
+            }
+        }
+    }
+
+    /// Changes the marks through the history, so it can be undone. `None` is an edit
+    /// that didn't apply here, and does nothing.
+    pub(super) fn edit(&mut self, edit: Option<Edit>) {
+        self.finish_editing();
+        if let Some(edit) = edit {
+            self.stop_repeating();
+            self.history.apply(edit, &mut self.marks, &mut self.spans);
+        }
+    }
+
+    /// Keeps track of what's being edited in place this frame. Moving on to something
+    /// else, or to nothing, records the last one in the history.
+    pub(super) fn follow_editing(&mut self, active: Option<Editing>) {
+        match (&self.editing, &active) {
+            (Some(cur), Some(active)) if cur.same_target(active) => {}
+            _ => {
+                self.finish_editing();
+                self.editing = active;
+            }
+        }
+    }
+
+    /// Records the change made in place since editing started, if there was one.
+    fn finish_editing(&mut self) {
+        let edit = match self.editing.take() {
+            Some(Editing::Mark(ind, before)) => edit::update(&self.marks, ind, before),
+            Some(Editing::Span(ind, before)) => edit::update_span(&self.spans, ind, before),
+            None => None,
+        };
+        if let Some(edit) = edit {
+            self.history.apply(edit, &mut self.marks, &mut self.spans);
+        }
+    }
+
+    /// Moves all marks by `secs` of file time.
+    pub(super) fn shift_marks(&mut self, secs: f32) {
+        if let Some(total) = self.audio.total_time() {
+            self.edit(edit::shift(&self.marks, secs, total));
+        }
+    }
+
+    /// Where Mark puts a new mark: back from the playhead by the reaction time while
+    /// playing, then onto the nearest pause if snapping is on.
+    pub(super) fn mark_position(&self) -> f32 {
+        let Some(total) = self.audio.total_time() else {
+            return self.cur_pos;
+        };
+        let total = total.as_secs_f32();
+        let mut secs = self.cur_pos * total;
+        if self.audio.is_playing() {
+            // the file moves on faster than the listener at higher speeds
+            secs -= self.mark_settings.reaction.as_secs_f32() * self.playback_speed;
+        }
+        if let (true, Some(pcm)) = (self.mark_settings.snap, self.audio.pcm()) {
+            let window = self.mark_settings.snap_window.as_secs_f32();
+            let start = Duration::from_secs_f32((secs - window).max(0.0));
+            let clip = pcm.slice(start, Duration::from_secs_f32((secs + window).max(0.0)));
+            let frame = (SNAP_FRAME.as_secs_f32() * pcm.sample_rate as f32) as usize;
+            if let Some(at) = analysis::quietest(&clip.mono(), frame) {
+                secs = start.as_secs_f32() + at as f32 / pcm.sample_rate as f32;
+            }
+        }
+        (secs / total).clamp(0.0, 1.0)
+    }
+
+    fn seek(&mut self, pos: f32) {
+        self.cur_pos = pos;
+        self.played_to = pos;
+        self.audio.scrub_to(pos);
+    }
+
+    /// Seeks to the pre-roll before `pos`, unless that's the start or end of the file.
+    fn seek_padded(&mut self, pos: f32) {
+        if 0.0 < pos && pos < 1.0 {
+            self.seek((pos - self.fraction(self.padding.pre)).max(0.0));
+        } else {
+            self.seek(pos);
+        }
+    }
+
+    /// `segment` with the pre- and post-roll added.
+    pub(super) fn padded(&self, (start, end): (f32, f32)) -> (f32, f32) {
+        let pre = self.fraction(self.padding.pre);
+        let post = self.fraction(self.padding.post);
+        ((start - pre).max(0.0), (end + post).min(1.0))
+    }
+
+    /// Where the playhead is for navigating, see [`navigation::through_preroll`].
+    fn nav_pos(&self) -> f32 {
+        let total = self.audio.total_time().unwrap_or_default();
+        if total.is_zero() {
+            return self.cur_pos;
+        }
+        navigation::through_preroll(&self.marks, self.cur_pos, total, self.padding.pre)
+    }
+
+    /// `dur` as a fraction of the file.
+    fn fraction(&self, dur: Duration) -> f32 {
+        self.audio
+            .total_time()
+            .map_or(0.0, |total| dur.as_secs_f32() / total.as_secs_f32())
+    }
+
+    /// Moves the playhead by `secs` of file time.
+    fn seek_by(&mut self, secs: f32) {
+        if let Some(total) = self.audio.total_time() {
+            let pos = self.cur_pos + secs / total.as_secs_f32();
+            self.seek(pos.clamp(0.0, 1.0));
+        }
+    }
+
+    pub(super) fn set_speed(&mut self, speed: f32) {
+        // the range of the side panel slider
+        self.playback_speed = speed.clamp(0.5, 3.0);
+        if let Some(source) = self.audio.source.as_mut() {
+            source.set_speed(self.playback_speed);
+        }
+    }
+
+    /// Changes the speed by `by`, landing on a multiple of it.
+    fn step_speed(&mut self, by: f32) {
+        self.set_speed(((self.playback_speed + by) / by.abs()).round() * by.abs());
+    }
+
+    /// The segment under the playhead, as fractions of the file.
+    pub(super) fn current_segment(&self) -> Option<(f32, f32)> {
+        let pos = self.nav_pos();
+        let start = marks::segment_start(&self.marks, pos);
+        let end = navigation::next(&self.marks, pos);
+        (start < end).then_some((start, end))
+    }
+
+    /// Notes the marks played through since the last frame, returning the index of the
+    /// last one.
+    pub(super) fn follow_playhead(&mut self) -> Option<usize> {
+        let (from, to) = (self.played_to, self.cur_pos);
+        self.played_to = to;
+        let total = self.audio.total_time().unwrap_or_default().as_secs_f32();
+        if self.audio.is_playing() && from < to && (to - from) * total < MAX_PLAYED.as_secs_f32() {
+            let crossed = self.marks.iter().rposition(|m| from < m.pos && m.pos <= to);
+            if let Some(ind) = crossed {
+                self.last_crossed = Some(self.marks[ind].pos);
+            }
+            return crossed;
+        }
+        None
+    }
+}
@@ -1,14 +1,34 @@
 use pvoc::{PhaseVocoder, Bin};
+use rodio::buffer::SamplesBuffer;
+use rodio::source::UniformSourceIterator;
 use rodio::Source;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::borrow::Borrow;
 use std::error::Error;
-use std::io::{Cursor, ErrorKind, Read};
+use std::io::{Cursor, Read};
+use std::ops::Range;
 use std::path;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::time::{self, Duration};
+use std::sync::{mpsc, Arc};
+use std::time;
+
+use super::filters::Filters;
+use super::transport::{Command, Transport, TransportHandle};
+
+/// Fade applied whenever playback (re)starts, so jumps don't click. Live playback can
+/// be given a different one, see [`AudioPlayer::set_fade_in`].
+pub const FADE_IN: time::Duration = time::Duration::from_millis(10);
+
+/// Speeding up and fading in, for clips and offline rendering, after the clean-up
+/// [`Filters`]. Live playback does the same another way: the filters come straight
+/// after the transport, the sink's speed can change while it plays, and the transport
+/// fades in after every jump, see [`AudioSource`].
+pub fn playback_chain<S>(source: S, speed: f32, fade_in: time::Duration) -> impl Source<Item = i16>
+where
+    S: Source<Item = i16>,
+{
+    source.speed(speed).fade_in(fade_in)
+}
 
 pub trait AudioContext {
     fn device(&self) -> &rodio::OutputStreamHandle;
@@ -59,9 +79,16 @@ impl SoundData {
         Ok(SoundData::from(buffer))
     }
 
-    pub fn can_play(&self) -> bool {
-        let cursor = Cursor::new(self.clone());
-        rodio::Decoder::new(cursor).is_ok()
+    /// Decodes the whole file into memory, for processing without an output device.
+    pub fn decode(&self) -> Result<Pcm, rodio::decoder::DecoderError> {
+        let decoder = rodio::Decoder::new(Cursor::new(self.clone()))?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        Ok(Pcm {
+            channels,
+            sample_rate,
+            samples: decoder.collect(),
+        })
     }
 }
 
@@ -91,64 +118,138 @@ impl AsRef<[u8]> for SoundData {
     }
 }
 
-pub struct SourceState {
-    data: Cursor<SoundData>,
-    repeat: bool,
-    fade_in: time::Duration,
-    pub speed: f32,
-    query_interval: time::Duration,
-    pub play_time: Arc<AtomicUsize>,
-    // pub total_play_time: usize,
-    total_length: Option<time::Duration>,
+/// Decoded, interleaved samples.
+#[derive(Clone, Debug)]
+pub struct Pcm {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
 }
 
-impl SourceState {
-    pub fn new(cursor: Cursor<SoundData>) -> Self {
-        let mut total_length = Some(time::Duration::from_secs(0));
-        if let Ok(d) = rodio::Decoder::new(cursor.clone()) {
-            total_length = d.total_duration();
-            // final attempt, this may be wrong though depending on the file type
-            if total_length.is_none() {
-                let ch = d.channels() as u64;
-                let sr = d.sample_rate() as u64;
-                let len = d.into_iter().count() as u64 * 1000 / (ch * sr);
-                total_length = Some(Duration::from_millis(len))
+impl Pcm {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        Pcm {
+            channels,
+            sample_rate,
+            samples: vec![],
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    pub fn duration(&self) -> time::Duration {
+        time::Duration::from_secs_f64(self.frames() as f64 / self.sample_rate as f64)
+    }
+
+    fn frame_at(&self, t: time::Duration) -> usize {
+        ((t.as_secs_f64() * self.sample_rate as f64) as usize).min(self.frames())
+    }
+
+    /// The samples between `start` and `end`, clamped to the length of the buffer.
+    pub fn slice(&self, start: time::Duration, end: time::Duration) -> Pcm {
+        let ch = self.channels as usize;
+        let (start, end) = (self.frame_at(start), self.frame_at(end));
+        Pcm {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            samples: self.samples[start * ch..end.max(start) * ch].to_vec(),
+        }
+    }
+
+    /// Channels averaged together and scaled to -1.0..=1.0, for analysis.
+    pub fn mono(&self) -> Vec<f32> {
+        self.samples
+            .chunks(self.channels as usize)
+            .map(|frame| {
+                frame.iter().map(|s| *s as f32).sum::<f32>()
+                    / (frame.len() as f32 * i16::MAX as f32)
+            })
+            .collect()
+    }
+
+    /// Linear fade in and out over `dur` at each end.
+    pub fn fade_edges(&mut self, dur: time::Duration) {
+        let ch = self.channels as usize;
+        let frames = self.frames();
+        let fade = ((dur.as_secs_f64() * self.sample_rate as f64) as usize).min(frames / 2);
+        for i in 0..fade {
+            let gain = i as f32 / fade as f32;
+            for c in 0..ch {
+                for ind in [i * ch + c, (frames - 1 - i) * ch + c] {
+                    self.samples[ind] = (self.samples[ind] as f32 * gain) as i16;
+                }
             }
         }
-        SourceState {
-            data: cursor,
-            repeat: false,
-            fade_in: time::Duration::from_millis(10),
-            speed: 1.0,
-            query_interval: time::Duration::from_millis(50),
-            play_time: Arc::new(AtomicUsize::new(0)),
-            total_length,
+    }
+
+    /// Multiplies every sample by `gain`, clipping at full scale.
+    pub fn amplify(&mut self, gain: f32) {
+        for s in self.samples.iter_mut() {
+            *s = (*s as f32 * gain).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
         }
     }
 
-    pub fn set_repeat(&mut self, repeat: bool) {
-        self.repeat = repeat;
+    pub fn append(&mut self, other: &Pcm) {
+        self.samples.extend_from_slice(&other.samples);
     }
 
-    pub fn set_fade_in(&mut self, dur: time::Duration) {
-        self.fade_in = dur;
+    pub fn append_silence(&mut self, dur: time::Duration) {
+        let len = (dur.as_secs_f64() * self.sample_rate as f64) as usize * self.channels as usize;
+        self.samples.resize(self.samples.len() + len, 0);
     }
 
-    pub fn set_speed(&mut self, ratio: f32) {
-        self.speed = ratio;
+    /// Runs the samples through `filters` and [`playback_chain`] and resamples back to
+    /// the original rate, so the result plays at `speed` in any player.
+    pub fn processed(&self, filters: &Filters, speed: f32, fade_in: time::Duration) -> Pcm {
+        let filtered = filters.process(self);
+        let source = SamplesBuffer::new(self.channels, self.sample_rate, filtered.samples);
+        let chain = playback_chain(source, speed, fade_in);
+        Pcm {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            samples: UniformSourceIterator::new(chain, self.channels, self.sample_rate).collect(),
+        }
     }
 
-    pub fn repeat(&self) -> bool {
-        self.repeat
+    pub fn write_wav<P: AsRef<path::Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let spec = hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)
+            .with_context(|| format!("couldn't create {}", path.display()))?;
+        for s in self.samples.iter() {
+            writer.write_sample(*s)?;
+        }
+        writer.finalize()?;
+        Ok(())
     }
+}
 
-    pub fn elapsed(&self) -> time::Duration {
-        let t = self.play_time.load(Ordering::SeqCst);
-        time::Duration::from_micros(t as u64)
+/// How the loaded file is played.
+pub struct SourceState {
+    /// Crossfade length at jumps, including going round a loop.
+    fade_in: time::Duration,
+    pub speed: f32,
+    total_length: Option<time::Duration>,
+}
+
+impl SourceState {
+    pub fn new(pcm: &Pcm, fade_in: time::Duration) -> Self {
+        SourceState {
+            fade_in,
+            speed: 1.0,
+            total_length: Some(pcm.duration()),
+        }
     }
 
-    pub fn set_query_interval(&mut self, t: time::Duration) {
-        self.query_interval = t;
+    pub fn set_speed(&mut self, ratio: f32) {
+        self.speed = ratio;
     }
 
     pub fn total_length(&self) -> Option<time::Duration> {
@@ -156,177 +257,126 @@ impl SourceState {
     }
 }
 
+/// A file playing through one sink for as long as it's loaded. Seeking and looping are
+/// commands to its [`Transport`], nothing is rebuilt.
 pub struct AudioSource {
     pub sink: rodio::Sink,
-    pub cursor_len: usize,
     pub state: SourceState,
+    transport: TransportHandle,
+    looping: Option<Range<usize>>,
+    /// Silence between loops, in frames.
+    gap: usize,
+    /// Silence on leaving the segment playing, see [`Command::GapAt`].
+    gap_at: Option<(usize, usize)>,
+    pcm: Arc<Pcm>,
 }
 
 impl AudioSource {
-    pub fn new<P: AsRef<path::Path>>(
-        audio_context: &dyn AudioContext,
-        path: P,
-    ) -> Result<Self, std::io::Error> {
-        let path = path.as_ref();
-        let data = SoundData::new(path)?;
-        AudioSource::from_data(audio_context, data)
-    }
-
-    pub fn from_data(
+    /// Plays `pcm` through the clean-up `filters`, which start out bypassed or not
+    /// according to their switches.
+    pub fn new(
         audio_context: &dyn AudioContext,
-        data: SoundData,
+        pcm: Pcm,
+        fade_in: time::Duration,
+        filters: &Filters,
     ) -> Result<Self, std::io::Error> {
-        if !data.can_play() {
-            return Err(std::io::Error::new(
-                ErrorKind::Other,
-                "Couldn't play the audio",
-            ));
-        }
-        let sink = rodio::Sink::try_new(audio_context.device());
-        if sink.is_err() {
-            return Err(std::io::Error::new(
-                ErrorKind::Other,
-                "Couldn't create the sink",
-            ));
-        }
-        let cursor_len = data.0.as_ref().len();
-        let cursor = Cursor::new(data);
+        let state = SourceState::new(&pcm, fade_in);
+        let sink = rodio::Sink::try_new(audio_context.device())
+            .map_err(|e| std::io::Error::other(format!("couldn't create the sink: {e}")))?;
+        let pcm = Arc::new(pcm);
+        let (transport, handle) = Transport::new(pcm.clone(), state.fade_in);
+        sink.pause();
+        sink.append(filters.chain(transport));
         Ok(AudioSource {
-            sink: sink.unwrap(),
-            state: SourceState::new(cursor),
-            cursor_len,
+            sink,
+            state,
+            transport: handle,
+            looping: None,
+            gap: 0,
+            gap_at: None,
+            pcm,
         })
     }
 
-    fn play_later(&mut self, start: bool) -> Result<(), rodio::decoder::DecoderError> {
-        let was_playing = self.playing() || start;
-        let cursor = self.state.data.clone();
-
-        let period_mus = self.state.query_interval.as_secs() as usize * 1_000_000
-            + self.state.query_interval.subsec_micros() as usize;
-        let total_periods = self.state.total_length.unwrap().as_secs_f32()
-            / self.state.query_interval.as_secs_f32();
-
-        let counter = self.state.play_time.clone();
-        counter.store(
-            ((period_mus as f32 * total_periods)
-                * (cursor.position() as f32 / self.cursor_len as f32)) as usize,
-            Ordering::SeqCst,
-        );
-        let sound = rodio::Decoder::new(cursor)?
-            .speed(self.state.speed)
-            .fade_in(self.state.fade_in)
-            .periodic_access(self.state.query_interval, move |a| {
-                let _ = counter.fetch_add(period_mus, Ordering::SeqCst);
-            });
-        let sample_rate = sound.sample_rate() as f64;
-        let channels = sound.channels() as usize;
-        let frame_size = 256;
-        let time_res = 4;
-        // let mut pvoc = PhaseVocoder::new(channels, sample_rate, frame_size, time_res);
-        // pvoc.process(
-        //     input,
-        //     output,
-        //     |channels: usize, bins: usize, input: &[Vec<Bin>], output: &mut [Vec<Bin>]| {
-        //         for i in 0..channels {
-        //             for j in 0..bins {
-        //                 output[i][j] = input[i][j]; // change this!
-        //             }
-        //         }
-        //     },
-        // );
-
-        self.sink.append(sound);
-        if !was_playing {
-            self.sink.pause();
-        }
-
-        Ok(())
-    }
-
-    fn set_repeat(&mut self, repeat: bool) {
-        self.state.set_repeat(repeat)
-    }
-
-    fn set_fade_in(&mut self, dur: time::Duration) {
-        self.state.set_fade_in(dur);
-    }
-
     pub fn set_speed(&mut self, ratio: f32) {
         self.state.set_speed(ratio);
         self.sink.set_speed(ratio);
     }
 
-    fn repeat(&self) -> bool {
-        self.state.repeat
+    fn set_fade_in(&mut self, fade_in: time::Duration) {
+        self.state.fade_in = fade_in;
+        self.transport
+            .send(Command::Fade(self.transport.frames_in(fade_in)));
     }
 
-    fn pause(&self) {
-        self.sink.pause()
-    }
-
-    fn resume(&mut self) {
-        if self.stopped() {
-            self.play_later(true).unwrap();
+    /// Sets the silence between loops, if it's changed.
+    pub fn set_gap(&mut self, gap: time::Duration) {
+        let frames = (gap.as_secs_f64() * self.transport.sample_rate as f64) as usize;
+        if frames != self.gap {
+            self.gap = frames;
+            self.transport.send(Command::Gap(frames));
         }
-        self.sink.play()
     }
 
-    fn stop(
-        &mut self,
-        audio_context: &dyn AudioContext,
-        clear_time: bool,
-    ) -> Result<(), rodio::PlayError> {
-        let volume = self.volume();
-        let device = audio_context.device();
-        self.sink = rodio::Sink::try_new(device)?;
-        self.sink.set_speed(self.state.speed);
-        if clear_time {
-            self.state.play_time.store(0, Ordering::SeqCst);
+    /// Sets the silence to play on reaching the end of the segment playing, if it's
+    /// changed.
+    fn set_gap_at(&mut self, gap: Option<(usize, usize)>) {
+        if gap != self.gap_at {
+            self.gap_at = gap;
+            self.transport.send(Command::GapAt(gap));
         }
-        self.set_volume(volume);
-        if clear_time {
-            self.play_later(false)?;
-        }
-        Ok(())
     }
 
-    fn stopped(&self) -> bool {
-        self.sink.empty()
+    /// Silences to play at points in the file, as `(at, length)`.
+    fn set_pauses(&mut self, pauses: &[(time::Duration, time::Duration)]) {
+        let rate = self.transport.sample_rate as f64;
+        let frames = |d: time::Duration| (d.as_secs_f64() * rate) as usize;
+        let pauses = pauses.iter().map(|(at, len)| (frames(*at), frames(*len)));
+        self.transport.send(Command::Pauses(pauses.collect()));
     }
 
-    fn volume(&self) -> f32 {
-        self.sink.volume()
+    /// Past the end of the file, with nothing to loop back from.
+    fn ended(&self) -> bool {
+        self.looping.is_none() && self.transport.position() >= self.transport.frames
     }
 
-    fn set_volume(&mut self, value: f32) {
-        self.sink.set_volume(value)
+    fn playing(&self) -> bool {
+        !self.sink.is_paused() && !self.ended()
     }
 
-    fn paused(&self) -> bool {
-        self.sink.is_paused()
+    fn seek(&mut self, frame: usize) {
+        self.transport.send(Command::Seek(frame));
     }
 
-    fn playing(&self) -> bool {
-        !self.paused() && !self.stopped()
+    fn set_loop(&mut self, frames: Option<Range<usize>>) {
+        self.looping = frames.clone();
+        self.transport.send(Command::Loop(frames));
     }
 
     fn elapsed(&self) -> time::Duration {
-        self.state.elapsed()
-    }
-
-    fn set_query_interval(&mut self, t: time::Duration) {
-        self.state.set_query_interval(t)
+        self.transport.time_at(self.transport.position())
     }
 
     fn total_time(&self) -> Option<time::Duration> {
         self.state.total_length()
     }
+
+    /// `point` as a frame, `point` being a fraction of the file like the slider value.
+    fn frame(&self, point: f32) -> usize {
+        (self.transport.frames as f64 * point.clamp(0.0, 1.0) as f64) as usize
+    }
 }
 
 pub struct AudioPlayer {
     audio_ctx: Box<dyn AudioContext>,
     pub source: Option<Box<AudioSource>>,
+    /// Kept for the next file loaded.
+    fade_in: time::Duration,
+    pub filters: Filters,
+    /// The file being read and decoded in the background, if any.
+    loading: Option<mpsc::Receiver<Result<Pcm>>>,
+    /// How many files have been loaded, to tell them apart.
+    loads: usize,
 }
 
 impl AudioPlayer {
@@ -342,15 +392,65 @@ impl AudioPlayer {
         AudioPlayer {
             audio_ctx,
             source: None,
+            fade_in: FADE_IN,
+            filters: Default::default(),
+            loading: None,
+            loads: 0,
         }
     }
 
-    pub fn load(&mut self, path: &str) -> Result<()> {
-        self.source = Some(Box::new(AudioSource::new(
-            self.audio_ctx.as_ref(),
-            path::Path::new(path),
-        )?));
-        Ok(())
+    /// Starts reading and decoding `path` on a worker thread, long files take a while.
+    /// Whatever was playing stops now; [`AudioPlayer::finish_load`] picks the new one up.
+    pub fn load(&mut self, path: &str) {
+        self.source = None;
+        let path = path.to_owned();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let pcm = SoundData::new(&path)
+                .with_context(|| format!("couldn't read {path}"))
+                .and_then(|data| {
+                    data.decode()
+                        .with_context(|| format!("couldn't decode {path}"))
+                });
+            _ = tx.send(pcm);
+        });
+        self.loading = Some(rx);
+    }
+
+    /// Starts playing the file [`AudioPlayer::load`] was decoding, once it's ready.
+    /// Returns how loading went, the one time it's done.
+    pub fn finish_load(&mut self) -> Option<Result<()>> {
+        let pcm = match self.loading.as_ref()?.try_recv() {
+            Ok(pcm) => pcm,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err(anyhow::anyhow!("loading stopped")),
+        };
+        self.loading = None;
+        Some(pcm.and_then(|pcm| {
+            let source =
+                AudioSource::new(self.audio_ctx.as_ref(), pcm, self.fade_in, &self.filters)?;
+            self.source = Some(Box::new(source));
+            self.loads += 1;
+            Ok(())
+        }))
+    }
+
+    /// Which load the playing file came from, so anything worked out from an earlier
+    /// file can tell it's stale.
+    pub fn load_id(&self) -> usize {
+        self.loads
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// How long jumps and going round a loop take to crossfade.
+    pub fn set_fade_in(&mut self, fade_in: time::Duration) {
+        self.fade_in = fade_in;
+        if let Some(s) = self.source.as_mut() {
+            s.set_fade_in(fade_in);
+        }
     }
 
     pub fn is_playing(&self) -> bool {
@@ -359,18 +459,30 @@ impl AudioPlayer {
 
     pub fn toggle_play(&mut self) {
         if let Some(s) = self.source.as_mut() {
-            if !s.playing() {
-                s.resume();
+            if s.playing() {
+                s.sink.pause();
             } else {
-                s.pause();
+                if s.ended() {
+                    s.seek(0);
+                }
+                s.sink.play();
             }
         }
     }
 
-    pub fn stop(&mut self, ctx: &dyn AudioContext) {
-        if let Some(s) = self.source.as_mut() {
-            s.stop(ctx, true).unwrap();
-        }
+    /// The loaded file, decoded. Anything working on the whole file should share this
+    /// rather than decode it again.
+    pub fn pcm(&self) -> Option<Arc<Pcm>> {
+        self.source.as_ref().map(|s| s.pcm.clone())
+    }
+
+    /// Plays `pcm` on its own sink, independent of the loaded file. Playback stops
+    /// when the returned sink is dropped.
+    pub fn play_clip(&self, pcm: &Pcm, speed: f32) -> Result<rodio::Sink> {
+        let sink = rodio::Sink::try_new(self.audio_ctx.device())?;
+        let source = SamplesBuffer::new(pcm.channels, pcm.sample_rate, pcm.samples.clone());
+        sink.append(playback_chain(self.filters.chain(source), speed, FADE_IN));
+        Ok(sink)
     }
 
     pub fn total_time(&self) -> Option<time::Duration> {
@@ -383,19 +495,59 @@ impl AudioPlayer {
 
     pub fn scrub_to(&mut self, point: f32) {
         if let Some(s) = self.source.as_mut() {
-            // println!("speed {}", s.state.speed);
-            s.state.data.set_position(
-                (((s.cursor_len - 1) as f32 * point) as usize)
-                    .try_into()
-                    .unwrap(),
-            );
-            // TODO: this should probably be some sort of clear, not "stop"
-            let was_playing = s.playing();
-            s.stop(self.audio_ctx.as_mut(), false).unwrap();
-            s.play_later(was_playing).ok();
+            let frame = s.frame(point);
+            s.seek(frame);
+        }
+    }
+
+    /// Keeps playing from `start` to `end` (fractions of the file) over and over, or
+    /// stops looping with `None`.
+    pub fn set_loop(&mut self, region: Option<(f32, f32)>) {
+        if let Some(s) = self.source.as_mut() {
+            let frames = region.map(|(start, end)| s.frame(start)..s.frame(end));
+            s.set_loop(frames);
+        }
+    }
+
+    /// Stops for a while at each of `pauses`, `(at, length)`, sorted by where they are.
+    pub fn set_pauses(&mut self, pauses: &[(time::Duration, time::Duration)]) {
+        if let Some(s) = self.source.as_mut() {
+            s.set_pauses(pauses);
         }
     }
 
+    /// Goes quiet for `gap` on reaching `end` (a fraction of the file), where the segment
+    /// playing ends, or not at all with `None`.
+    pub fn set_segment_gap(&mut self, gap: Option<(f32, time::Duration)>) {
+        if let Some(s) = self.source.as_mut() {
+            let frames = gap.map(|(end, gap)| {
+                let len = (gap.as_secs_f64() * s.transport.sample_rate as f64) as usize;
+                (s.frame(end), len)
+            });
+            s.set_gap_at(frames);
+        }
+    }
+
+    /// How many times playback has gone round a loop since the file was loaded.
+    pub fn loops(&self) -> usize {
+        self.source.as_ref().map_or(0, |s| s.transport.loops())
+    }
+
+    /// How long gaps and pauses have kept playback silent since the file was loaded.
+    pub fn silence(&self) -> time::Duration {
+        self.source.as_ref().map_or(time::Duration::ZERO, |s| {
+            s.transport.time_at(s.transport.silent_frames())
+        })
+    }
+
+    /// The looped stretch, as fractions of the file.
+    pub fn loop_region(&self) -> Option<(f32, f32)> {
+        let s = self.source.as_ref()?;
+        let frames = s.transport.frames as f32;
+        let range = s.looping.as_ref()?;
+        Some((range.start as f32 / frames, range.end as f32 / frames))
+    }
+
     pub fn play_time(&self) -> time::Duration {
         if let Some(s) = self.source.as_ref() {
             s.elapsed()
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use super::actions::Editing;
+use super::input::Button;
+use super::TemplateApp;
+use crate::edit;
+use crate::marks::Span;
+
+/// What's being held down to capture a span; letting go of it ends the span.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Holder {
+    Key(egui::Key),
+    Button(Button),
+    Pointer,
+    /// Started without anything to hold, ends the next time it's asked for.
+    Toggle,
+}
+
+impl TemplateApp {
+    /// Starts a span at the playhead, unless one is already being captured.
+    pub(super) fn begin_capture(&mut self, holder: Holder) {
+        if self.capture.is_none() {
+            self.capture = Some((self.mark_position(), holder));
+        }
+    }
+
+    /// Finishes the span being captured, if any. Empty spans are dropped.
+    pub(super) fn end_capture(&mut self) {
+        let Some((start, _)) = self.capture.take() else {
+            return;
+        };
+        let end = self.mark_position();
+        if end > start {
+            self.edit(Some(edit::add_span(&self.spans, Span::new(start, end))));
+        }
+    }
+
+    /// Ends a capture held with a key once the key is let go.
+    pub(super) fn check_capture_key(&mut self, ctx: &egui::Context) {
+        if let Some((_, Holder::Key(key))) = self.capture {
+            if !ctx.input().key_down(key) {
+                self.end_capture();
+            }
+        }
+    }
+
+    /// Starts or ends a capture held with the on-screen button.
+    pub(super) fn hold_capture_pointer(&mut self, held: bool) {
+        match self.capture {
+            None if held => self.begin_capture(Holder::Pointer),
+            Some((_, Holder::Pointer)) if !held => self.end_capture(),
+            _ => {}
+        }
+    }
+}
+
+/// What was asked for in the list of captured spans.
+#[derive(Default)]
+pub struct SpansPicked {
+    /// A position to jump to.
+    pub jump: Option<f32>,
+    pub delete: Option<usize>,
+    /// The span whose label is being typed in.
+    pub editing: Option<Editing>,
+}
+
+/// The captured spans, with their times. Labels are typed in place; deleting is left
+/// to the caller so it goes through the history.
+pub fn spans_ui(ui: &mut egui::Ui, spans: &mut [Span], total: Duration) -> SpansPicked {
+    let mut picked = SpansPicked::default();
+    let secs = |pos: f32| pos * total.as_secs_f32();
+    ui.label("Captured segments");
+    egui::Grid::new("spans_grid").striped(true).show(ui, |ui| {
+        ui.strong("Start");
+        ui.strong("End");
+        ui.strong("Length");
+        ui.end_row();
+        for (ind, span) in spans.iter_mut().enumerate() {
+            let before = span.clone();
+            ui.label(format!("{:.2}s", secs(span.start)));
+            ui.label(format!("{:.2}s", secs(span.end)));
+            ui.label(format!("{:.2}s", secs(span.end - span.start)));
+            let label = ui.add(
+                egui::TextEdit::singleline(&mut span.label)
+                    .hint_text("label")
+                    .desired_width(160.0),
+            );
+            if label.has_focus() {
+                picked.editing = Some(Editing::Span(ind, before));
+            }
+            if ui.button("Jump").clicked() {
+                picked.jump = Some(span.start);
+            }
+            if ui.button("Delete").clicked() {
+                picked.delete = Some(ind);
+            }
+            ui.end_row();
+        }
+    });
+    picked
+}
@@ -0,0 +1,347 @@
+use egui::plot::{Legend, Line, Plot, PlotPoints, PlotUi, Polygon};
+use egui::Color32;
+
+use super::audio::Pcm;
+use super::recorder::Recorder;
+use super::TemplateApp;
+use crate::align::{self, Alignment, DivergenceKind, Features};
+use crate::analysis;
+use crate::marks;
+
+const MODEL_COLOR: Color32 = Color32::from_rgb(90, 170, 255);
+const TAKE_COLOR: Color32 = Color32::from_rgb(255, 150, 60);
+const TIMING_COLOR: Color32 = Color32::from_rgba_premultiplied(90, 90, 0, 60);
+const PITCH_COLOR: Color32 = Color32::from_rgba_premultiplied(110, 30, 30, 60);
+
+/// A piece of audio with what the compare window draws of it.
+pub struct Analysed {
+    pub pcm: Pcm,
+    features: Features,
+    /// RMS every [`align::HOP`].
+    envelope: Vec<f32>,
+    /// Semitones from the speaker's median F0, every [`align::HOP`].
+    pitch: Vec<Option<f32>>,
+}
+
+impl Analysed {
+    pub fn new(pcm: Pcm) -> Self {
+        let mono = pcm.mono();
+        let frame = (align::HOP.as_secs_f64() * pcm.sample_rate as f64) as usize;
+        let features = Features::new(&mono, pcm.sample_rate);
+        Self {
+            envelope: analysis::frame_rms(&mono, frame),
+            pitch: analysis::relative_semitones(&features.f0),
+            features,
+            pcm,
+        }
+    }
+
+    /// A recording with the silence before and after the speech cut off.
+    pub fn trimmed(pcm: Pcm) -> Self {
+        let speech = analysis::detect_segments(&pcm.mono(), pcm.sample_rate, &Default::default());
+        match (speech.first(), speech.last()) {
+            (Some((start, _)), Some((_, end))) => Self::new(pcm.slice(*start, *end)),
+            _ => Self::new(pcm),
+        }
+    }
+
+    fn envelope_line(&self, sign: f64, warp: Option<&[f32]>) -> PlotPoints {
+        frame_times(self.envelope.len(), warp)
+            .into_iter()
+            .map(|(t, i)| [t, sign * self.envelope[i] as f64])
+            .collect()
+    }
+
+    /// One line per voiced stretch, so unvoiced gaps stay empty.
+    fn pitch_lines(&self, warp: Option<&[f32]>) -> Vec<PlotPoints> {
+        let mut lines: Vec<Vec<[f64; 2]>> = vec![];
+        let mut voiced = false;
+        for (t, i) in frame_times(self.pitch.len(), warp) {
+            match self.pitch[i] {
+                Some(st) if voiced => lines.last_mut().unwrap().push([t, st as f64]),
+                Some(st) => lines.push(vec![[t, st as f64]]),
+                None => {}
+            }
+            voiced = self.pitch[i].is_some();
+        }
+        lines.into_iter().map(PlotPoints::from).collect()
+    }
+}
+
+/// Where to draw each of `len` frames: `(seconds, frame)`. With `warp`, the frames
+/// matched to each model frame are drawn at the model's time instead of their own.
+fn frame_times(len: usize, warp: Option<&[f32]>) -> Vec<(f64, usize)> {
+    let step = align::HOP.as_secs_f64();
+    if len == 0 {
+        return vec![];
+    }
+    match warp {
+        Some(warp) => warp
+            .iter()
+            .enumerate()
+            .map(|(i, j)| (i as f64 * step, (j.round() as usize).min(len - 1)))
+            .collect(),
+        None => (0..len).map(|i| (i as f64 * step, i)).collect(),
+    }
+}
+
+/// Shades the stretches of the model where the take diverged, between `bottom` and `top`.
+fn shade_divergences(plot_ui: &mut PlotUi, alignment: &Alignment, bottom: f64, top: f64) {
+    for d in alignment.divergences.iter() {
+        let (start, end) = (d.start.as_secs_f64(), d.end.as_secs_f64());
+        let (color, name) = match d.kind {
+            DivergenceKind::Timing => (TIMING_COLOR, "Timing"),
+            DivergenceKind::Pitch => (PITCH_COLOR, "Pitch"),
+        };
+        let rect = vec![[start, bottom], [end, bottom], [end, top], [start, top]];
+        plot_ui.polygon(
+            Polygon::new(rect)
+                .color(color)
+                .fill_alpha(1.0)
+                .width(0.0)
+                .name(name),
+        );
+    }
+}
+
+/// The model segment and the learner's take of it.
+pub struct Comparison {
+    pub model: Option<Analysed>,
+    pub take: Option<Analysed>,
+    pub alignment: Option<Alignment>,
+    /// Draw the take on the model's timeline, as matched by the alignment.
+    warp: bool,
+    recorder: Option<Recorder>,
+    sink: Option<rodio::Sink>,
+    status: Option<String>,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Self {
+            model: None,
+            take: None,
+            alignment: None,
+            warp: true,
+            recorder: None,
+            sink: None,
+            status: None,
+        }
+    }
+}
+
+impl Comparison {
+    fn realign(&mut self) {
+        self.alignment = match (self.model.as_ref(), self.take.as_ref()) {
+            (Some(model), Some(take)) => Some(align::align(&model.features, &take.features)),
+            _ => None,
+        };
+    }
+}
+
+impl TemplateApp {
+    /// Uses the segment under the playhead as the model.
+    fn compare_current_segment(&mut self) -> anyhow::Result<()> {
+        let (Some(pcm), Some(total)) = (self.audio.pcm(), self.audio.total_time()) else {
+            anyhow::bail!("no audio file is loaded");
+        };
+        let now = total.mul_f32(self.cur_pos);
+        let Some(segment) = marks::segments(&self.marks, total)
+            .into_iter()
+            .find(|s| s.start <= now && now < s.end)
+        else {
+            anyhow::bail!("no segment under the playhead");
+        };
+        self.compare.model = Some(Analysed::new(pcm.slice(segment.start, segment.end)));
+        self.compare.realign();
+        Ok(())
+    }
+
+    pub(super) fn toggle_recording(&mut self) {
+        let compare = &mut self.compare;
+        if let Some(recorder) = compare.recorder.take() {
+            compare.take = Some(Analysed::trimmed(recorder.finish()));
+            compare.realign();
+            return;
+        }
+        compare.sink = None;
+        if self.audio.is_playing() {
+            self.audio.toggle_play();
+        }
+        match Recorder::start() {
+            Ok(recorder) => {
+                compare.recorder = Some(recorder);
+                compare.status = None;
+            }
+            Err(e) => compare.status = Some(format!("couldn't record: {e:#}")),
+        }
+    }
+
+    fn play_compared(&mut self, take: bool) {
+        let compare = &mut self.compare;
+        let audio = if take { &compare.take } else { &compare.model };
+        let Some(audio) = audio else {
+            return;
+        };
+        compare.sink = None;
+        match self.audio.play_clip(&audio.pcm, 1.0) {
+            Ok(sink) => compare.sink = Some(sink),
+            Err(e) => compare.status = Some(format!("couldn't play: {e:#}")),
+        }
+    }
+
+    pub(super) fn compare_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.compare_open;
+        let mut play = None;
+        let mut record = false;
+        egui::Window::new("Compare with model")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Use current segment").clicked() {
+                        if let Err(e) = self.compare_current_segment() {
+                            self.compare.status = Some(format!("{e:#}"));
+                        }
+                    }
+                    if ui
+                        .add_enabled(
+                            self.compare.model.is_some(),
+                            egui::Button::new("Play model"),
+                        )
+                        .clicked()
+                    {
+                        play = Some(false);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if let Some(recorder) = self.compare.recorder.as_ref() {
+                        if ui.button("Stop").clicked() {
+                            record = true;
+                        }
+                        ui.label(format!(
+                            "recording {:.1}s",
+                            recorder.elapsed().as_secs_f32()
+                        ));
+                        if let Some(error) = recorder.error() {
+                            self.compare.status = Some(error);
+                        }
+                        ctx.request_repaint();
+                    } else if ui.button("Record take").clicked() {
+                        record = true;
+                    }
+                    if ui
+                        .add_enabled(self.compare.take.is_some(), egui::Button::new("Play take"))
+                        .clicked()
+                    {
+                        play = Some(true);
+                    }
+                });
+                if let Some(status) = self.compare.status.as_ref() {
+                    ui.label(status);
+                }
+
+                if let Some(alignment) = self.compare.alignment.as_ref() {
+                    ui.horizontal(|ui| {
+                        ui.strong(format!("Similarity: {:.0}/100", alignment.score));
+                        ui.checkbox(&mut self.compare.warp, "Align take to model");
+                    });
+                    for d in alignment.divergences.iter() {
+                        let what = match d.kind {
+                            DivergenceKind::Timing if d.amount > 1.0 => {
+                                format!("{:.1}× slower", d.amount)
+                            }
+                            DivergenceKind::Timing => format!("{:.1}× faster", 1.0 / d.amount),
+                            DivergenceKind::Pitch => format!("pitch {:+.1} semitones", d.amount),
+                        };
+                        ui.label(format!(
+                            "{:.2}s – {:.2}s: {what}",
+                            d.start.as_secs_f32(),
+                            d.end.as_secs_f32()
+                        ));
+                    }
+                }
+
+                let compare = &self.compare;
+                let alignment = compare.alignment.as_ref();
+                let warp = alignment.filter(|_| compare.warp).map(|a| a.take_frames());
+                let takes = [
+                    (&compare.model, "Model", MODEL_COLOR, 1.0, None),
+                    (&compare.take, "Take", TAKE_COLOR, -1.0, warp.as_deref()),
+                ];
+                let len = takes
+                    .iter()
+                    .filter_map(|(a, ..)| a.as_ref())
+                    .map(|a| a.pcm.duration().as_secs_f64())
+                    .fold(1.0, f64::max);
+
+                ui.label("Waveform");
+                Plot::new("compare_waveform")
+                    .height(100.0)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .allow_scroll(false)
+                    .include_x(0.0)
+                    .include_x(len)
+                    .show_axes([true, false])
+                    .show(ui, |plot_ui| {
+                        let peak = takes
+                            .iter()
+                            .filter_map(|(a, ..)| a.as_ref())
+                            .flat_map(|a| a.envelope.iter())
+                            .fold(0.0, |peak: f32, r| peak.max(*r))
+                            as f64;
+                        if let Some(alignment) = alignment {
+                            shade_divergences(plot_ui, alignment, -peak, peak);
+                        }
+                        for (audio, name, color, sign, warp) in takes.iter() {
+                            if let Some(audio) = audio {
+                                plot_ui.line(
+                                    Line::new(audio.envelope_line(*sign, *warp))
+                                        .color(*color)
+                                        .name(name),
+                                );
+                            }
+                        }
+                    });
+
+                ui.label("Pitch (semitones from each speaker's median)");
+                Plot::new("compare_pitch")
+                    .height(160.0)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .allow_scroll(false)
+                    .include_x(0.0)
+                    .include_x(len)
+                    .include_y(-6.0)
+                    .include_y(6.0)
+                    .legend(Legend::default())
+                    .show(ui, |plot_ui| {
+                        if let Some(alignment) = alignment {
+                            shade_divergences(plot_ui, alignment, -12.0, 12.0);
+                        }
+                        for (audio, name, color, _, warp) in takes.iter() {
+                            if let Some(audio) = audio {
+                                for line in audio.pitch_lines(*warp) {
+                                    plot_ui
+                                        .line(Line::new(line).color(*color).width(2.0).name(name));
+                                }
+                            }
+                        }
+                    });
+            });
+
+        if record {
+            self.toggle_recording();
+        }
+        if let Some(take) = play {
+            self.play_compared(take);
+        }
+        if !open {
+            self.compare.sink = None;
+            if let Some(recorder) = self.compare.recorder.take() {
+                recorder.finish();
+            }
+        }
+        self.compare_open = open;
+    }
+}
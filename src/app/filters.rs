@@ -0,0 +1,596 @@
+use rodio::buffer::SamplesBuffer;
+use rodio::Source;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::audio::Pcm;
+use super::TemplateApp;
+
+/// The voice band. Below is rumble and handling noise, above is mostly hiss; speech is
+/// perfectly clear without either.
+const VOICE_LOW: f32 = 100.0;
+const VOICE_HIGH: f32 = 7000.0;
+/// Where consonants get their definition.
+const PRESENCE_FREQ: f32 = 3000.0;
+const PRESENCE_DB: f32 = 5.0;
+const PRESENCE_Q: f32 = 0.8;
+/// FFT length for noise reduction, about 23ms at 44.1kHz. Frames overlap by half.
+const FRAME: usize = 1024;
+const HOP: usize = FRAME / 2;
+/// How many times the noise spectrum to take away. Over 1 gets more of the noise, at
+/// the cost of a warbly "musical noise" on what's left.
+const OVER_SUBTRACTION: f32 = 1.5;
+/// No bin is turned down further than this, which keeps speech from going watery.
+const SPECTRAL_FLOOR: f32 = 0.05;
+
+/// Which clean-up filters are on.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FilterSettings {
+    pub voice_band: bool,
+    pub denoise: bool,
+    pub presence: bool,
+}
+
+/// Switches a filter in the playback chain off while it's playing. Set means bypassed:
+/// the filter passes its input straight through.
+#[derive(Clone, Debug)]
+pub struct Bypass(Arc<AtomicBool>);
+
+impl Bypass {
+    pub fn new(bypassed: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(bypassed)))
+    }
+
+    pub fn set(&self, bypassed: bool) {
+        self.0.store(bypassed, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The spectrum of the noise [`Denoise`] takes out, see [`noise_profile`]. Nothing is
+/// taken out until one's been set.
+#[derive(Clone, Default)]
+pub struct NoiseProfile(Arc<Mutex<Option<Arc<Vec<f32>>>>>);
+
+impl NoiseProfile {
+    pub fn set(&self, profile: Option<Vec<f32>>) {
+        *self.0.lock().unwrap() = profile.map(Arc::new);
+    }
+
+    /// The current profile, unless the UI is setting it right now. The audio thread
+    /// mustn't wait.
+    fn try_get(&self) -> Option<Option<Arc<Vec<f32>>>> {
+        self.0.try_lock().ok().map(|p| p.clone())
+    }
+}
+
+/// The controls of a playback chain built by [`Filters::chain`].
+#[derive(Clone)]
+pub struct Filters {
+    pub voice_band: Bypass,
+    pub denoise: Bypass,
+    pub presence: Bypass,
+    pub noise: NoiseProfile,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self {
+            voice_band: Bypass::new(true),
+            denoise: Bypass::new(true),
+            presence: Bypass::new(true),
+            noise: Default::default(),
+        }
+    }
+}
+
+impl Filters {
+    pub fn apply(&self, settings: &FilterSettings) {
+        self.voice_band.set(!settings.voice_band);
+        self.denoise.set(!settings.denoise);
+        self.presence.set(!settings.presence);
+    }
+
+    /// `source` through the voice band filter, noise reduction and presence boost, in
+    /// that order.
+    pub fn chain<S>(&self, source: S) -> impl Source<Item = i16> + Send + 'static
+    where
+        S: Source<Item = i16> + Send + 'static,
+    {
+        let source = source.convert_samples::<f32>();
+        let source = Biquad::high_pass(source, VOICE_LOW, self.voice_band.clone());
+        let source = Biquad::low_pass(source, VOICE_HIGH, self.voice_band.clone());
+        let source = Denoise::new(source, self.noise.clone(), self.denoise.clone());
+        let source = Biquad::peaking(
+            source,
+            PRESENCE_FREQ,
+            PRESENCE_DB,
+            PRESENCE_Q,
+            self.presence.clone(),
+        );
+        source.convert_samples()
+    }
+
+    /// A copy that keeps the switches and noise profile as they are now, for work in
+    /// the background that shouldn't change halfway through.
+    pub fn fixed(&self) -> Filters {
+        let noise = self.noise.0.lock().unwrap().clone();
+        Filters {
+            voice_band: Bypass::new(self.voice_band.get()),
+            denoise: Bypass::new(self.denoise.get()),
+            presence: Bypass::new(self.presence.get()),
+            noise: NoiseProfile(Arc::new(Mutex::new(noise))),
+        }
+    }
+
+    /// `pcm` through the chain, lined up with it: the half frame [`Denoise`] holds
+    /// everything back is taken off the front.
+    pub fn process(&self, pcm: &Pcm) -> Pcm {
+        if self.voice_band.get() && self.denoise.get() && self.presence.get() {
+            return pcm.clone();
+        }
+        let source = SamplesBuffer::new(pcm.channels, pcm.sample_rate, pcm.samples.clone());
+        let samples = self
+            .chain(source)
+            .skip(HOP * pcm.channels as usize)
+            .take(pcm.samples.len())
+            .collect();
+        Pcm { samples, ..*pcm }
+    }
+}
+
+/// Second order filter coefficients, from the Audio EQ Cookbook.
+#[derive(Clone, Copy, Debug)]
+struct Coeffs {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coeffs {
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Self {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+        }
+    }
+
+    /// Angular frequency and `alpha` for a filter at `hz` with quality `q`.
+    fn shape(hz: f32, q: f32, sample_rate: u32) -> (f32, f32) {
+        let w0 = 2.0 * PI * hz.min(sample_rate as f32 * 0.45) / sample_rate as f32;
+        (w0, w0.sin() / (2.0 * q))
+    }
+
+    fn high_pass(hz: f32, sample_rate: u32) -> Self {
+        let (w0, alpha) = Self::shape(hz, 0.707, sample_rate);
+        let cos = w0.cos();
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn low_pass(hz: f32, sample_rate: u32) -> Self {
+        let (w0, alpha) = Self::shape(hz, 0.707, sample_rate);
+        let cos = w0.cos();
+        Self::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn peaking(hz: f32, db: f32, q: f32, sample_rate: u32) -> Self {
+        let (w0, alpha) = Self::shape(hz, q, sample_rate);
+        let a = 10f32.powf(db / 40.0);
+        let cos = w0.cos();
+        Self::new(
+            [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+            [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+        )
+    }
+}
+
+/// A second order filter on each channel of `source`.
+pub struct Biquad<S> {
+    source: S,
+    coeffs: Coeffs,
+    /// Last two inputs and outputs, per channel.
+    state: Vec<[f32; 4]>,
+    channel: usize,
+    bypass: Bypass,
+}
+
+impl<S: Source<Item = f32>> Biquad<S> {
+    fn new(source: S, coeffs: Coeffs, bypass: Bypass) -> Self {
+        Self {
+            state: vec![[0.0; 4]; source.channels() as usize],
+            source,
+            coeffs,
+            channel: 0,
+            bypass,
+        }
+    }
+
+    /// Cuts everything below `hz`, 12dB an octave.
+    pub fn high_pass(source: S, hz: f32, bypass: Bypass) -> Self {
+        let coeffs = Coeffs::high_pass(hz, source.sample_rate());
+        Self::new(source, coeffs, bypass)
+    }
+
+    /// Cuts everything above `hz`, 12dB an octave.
+    pub fn low_pass(source: S, hz: f32, bypass: Bypass) -> Self {
+        let coeffs = Coeffs::low_pass(hz, source.sample_rate());
+        Self::new(source, coeffs, bypass)
+    }
+
+    /// Boosts (or cuts) by `db` around `hz`, over a width set by `q`.
+    pub fn peaking(source: S, hz: f32, db: f32, q: f32, bypass: Bypass) -> Self {
+        let coeffs = Coeffs::peaking(hz, db, q, source.sample_rate());
+        Self::new(source, coeffs, bypass)
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Biquad<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.source.next()?;
+        let c = &self.coeffs;
+        let [x1, x2, y1, y2] = self.state[self.channel];
+        let y = c.b0 * x + c.b1 * x1 + c.b2 * x2 - c.a1 * y1 - c.a2 * y2;
+        // keep filtering while bypassed, so switching back in doesn't click
+        self.state[self.channel] = [x, x1, y, y1];
+        self.channel = (self.channel + 1) % self.state.len();
+        Some(if self.bypass.get() { x } else { y })
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Biquad<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+/// Square root of a Hann window: applied before and after, overlapping by half, the
+/// frames add back up to the original.
+fn window() -> Vec<f32> {
+    (0..FRAME)
+        .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME as f32).cos()).sqrt())
+        .collect()
+}
+
+/// The average magnitude spectrum of mono `samples`, for [`Denoise`] to take away.
+/// `None` if there isn't a frame's worth.
+pub fn noise_profile(samples: &[f32]) -> Option<Vec<f32>> {
+    if samples.len() < FRAME {
+        return None;
+    }
+    let fft = FftPlanner::new().plan_fft_forward(FRAME);
+    let window = window();
+    let mut sum = vec![0.0; FRAME / 2 + 1];
+    let mut frames = 0;
+    for start in (0..=samples.len() - FRAME).step_by(HOP) {
+        let mut buffer: Vec<_> = samples[start..start + FRAME]
+            .iter()
+            .zip(&window)
+            .map(|(s, w)| Complex::new(s * w, 0.0))
+            .collect();
+        fft.process(&mut buffer);
+        for (sum, bin) in sum.iter_mut().zip(&buffer) {
+            *sum += bin.norm();
+        }
+        frames += 1;
+    }
+    Some(sum.into_iter().map(|s| s / frames as f32).collect())
+}
+
+/// Spectral subtraction: each channel's spectrum, less the noise profile's, a frame at
+/// a time. Delays the audio by half a frame, bypassed or not, so switching doesn't jump.
+pub struct Denoise<S> {
+    source: S,
+    noise: NoiseProfile,
+    profile: Option<Arc<Vec<f32>>>,
+    bypass: Bypass,
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    /// The last frame of input, and the output being added up, per channel.
+    input: Vec<Vec<f32>>,
+    overlap: Vec<Vec<f32>>,
+    out: VecDeque<f32>,
+    /// Silent frames fed in since the source ran out, to get the last of it out.
+    flushed: usize,
+}
+
+impl<S: Source<Item = f32>> Denoise<S> {
+    pub fn new(source: S, noise: NoiseProfile, bypass: Bypass) -> Self {
+        let channels = source.channels() as usize;
+        let mut planner = FftPlanner::new();
+        Self {
+            source,
+            noise,
+            profile: None,
+            bypass,
+            fft: planner.plan_fft_forward(FRAME),
+            ifft: planner.plan_fft_inverse(FRAME),
+            window: window(),
+            buffer: vec![Complex::new(0.0, 0.0); FRAME],
+            input: vec![vec![0.0; FRAME]; channels],
+            overlap: vec![vec![0.0; FRAME]; channels],
+            out: VecDeque::with_capacity(HOP * channels),
+            flushed: 0,
+        }
+    }
+
+    /// Reads a hop's worth of input and puts a hop's worth of output in `out`. Returns
+    /// `false` once everything's out.
+    fn fill(&mut self) -> bool {
+        if self.flushed >= FRAME {
+            return false;
+        }
+        let channels = self.input.len();
+        for input in self.input.iter_mut() {
+            input.copy_within(HOP.., 0);
+        }
+        let mut missing: usize = 0;
+        for i in FRAME - HOP..FRAME {
+            for input in self.input.iter_mut() {
+                input[i] = self.source.next().unwrap_or_else(|| {
+                    missing += 1;
+                    0.0
+                });
+            }
+        }
+        self.flushed += missing.div_ceil(channels);
+
+        if let Some(profile) = self.noise.try_get() {
+            self.profile = profile;
+        }
+        let profile = self.profile.as_ref().filter(|_| !self.bypass.get());
+        for (input, overlap) in self.input.iter().zip(self.overlap.iter_mut()) {
+            for ((b, s), w) in self.buffer.iter_mut().zip(input).zip(&self.window) {
+                *b = Complex::new(s * w, 0.0);
+            }
+            self.fft.process(&mut self.buffer);
+            if let Some(profile) = profile {
+                for (k, bin) in self.buffer.iter_mut().enumerate() {
+                    let mag = bin.norm();
+                    if mag > 0.0 {
+                        let noise = profile[k.min(FRAME - k)];
+                        let keep = (mag - OVER_SUBTRACTION * noise).max(SPECTRAL_FLOOR * mag);
+                        *bin *= keep / mag;
+                    }
+                }
+            }
+            self.ifft.process(&mut self.buffer);
+            for ((o, b), w) in overlap.iter_mut().zip(&self.buffer).zip(&self.window) {
+                *o += b.re * w / FRAME as f32;
+            }
+        }
+        for i in 0..HOP {
+            for overlap in self.overlap.iter() {
+                self.out.push_back(overlap[i]);
+            }
+        }
+        for overlap in self.overlap.iter_mut() {
+            overlap.copy_within(HOP.., 0);
+            overlap[FRAME - HOP..].fill(0.0);
+        }
+        true
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Denoise<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.out.is_empty() && !self.fill() {
+            return None;
+        }
+        self.out.pop_front()
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Denoise<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.len() as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+impl TemplateApp {
+    /// Takes the noise profile from the looped stretch, or failing that the segment under
+    /// the playhead. It should be something with no speech in it.
+    pub(super) fn pick_noise_sample(&mut self) {
+        let (Some(pcm), Some(total)) = (self.audio.pcm(), self.audio.total_time()) else {
+            return;
+        };
+        let Some((start, end)) = self.audio.loop_region().or_else(|| self.current_segment()) else {
+            return;
+        };
+        let clip = pcm.slice(total.mul_f32(start), total.mul_f32(end));
+        let profile = noise_profile(&clip.mono());
+        self.noise_status = Some(match profile {
+            Some(_) => format!("noise sample: {:.1}s", clip.duration().as_secs_f32()),
+            None => "noise sample too short".to_owned(),
+        });
+        self.audio.filters.noise.set(profile);
+    }
+}
+
+/// The filter switches. Returns `true` if a noise sample should be taken.
+pub fn filters_ui(ui: &mut egui::Ui, settings: &mut FilterSettings, status: Option<&str>) -> bool {
+    ui.checkbox(&mut settings.voice_band, "Voice band filter")
+        .on_hover_text(format!("only {VOICE_LOW} Hz to {VOICE_HIGH} Hz"));
+    ui.checkbox(&mut settings.denoise, "Noise reduction");
+    let pick = ui
+        .add_enabled(settings.denoise, egui::Button::new("Use as noise sample"))
+        .on_hover_text("the loop, or the segment under the playhead, with no speech in it")
+        .clicked();
+    if let Some(status) = status {
+        ui.weak(status);
+    }
+    ui.checkbox(&mut settings.presence, "Presence boost")
+        .on_hover_text(format!("+{PRESENCE_DB} dB around {PRESENCE_FREQ} Hz"));
+    pick
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const RATE: u32 = 16_000;
+
+    fn sine(hz: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * hz * i as f32 / RATE as f32).sin() * 0.5)
+            .collect()
+    }
+
+    /// Deterministic white-ish noise.
+    fn noise(len: usize) -> Vec<f32> {
+        let mut x = 1u32;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (x >> 8) as f32 / (1 << 24) as f32 * 0.2 - 0.1
+            })
+            .collect()
+    }
+
+    fn source(samples: &[f32]) -> SamplesBuffer<f32> {
+        SamplesBuffer::new(1, RATE, samples.to_vec())
+    }
+
+    /// RMS of `samples`, skipping the first tenth while filters settle.
+    fn rms(samples: &[f32]) -> f32 {
+        let settled = &samples[samples.len() / 10..];
+        (settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32).sqrt()
+    }
+
+    fn gain<S: Source<Item = f32>>(input: &[f32], filtered: S) -> f32 {
+        let out: Vec<f32> = filtered.collect();
+        rms(&out[..input.len()]) / rms(input)
+    }
+
+    #[test]
+    fn voice_band_passes_speech() {
+        let on = Bypass::new(false);
+        let speech = sine(500.0, 8000);
+        let rumble = sine(30.0, 8000);
+        let hiss = sine(7900.0, 8000);
+        let high = |s: &[f32]| gain(s, Biquad::high_pass(source(s), VOICE_LOW, on.clone()));
+        let low = |s: &[f32]| gain(s, Biquad::low_pass(source(s), 2000.0, on.clone()));
+        assert!(high(&speech) > 0.95);
+        assert!(high(&rumble) < 0.15);
+        assert!(low(&speech) > 0.95);
+        assert!(low(&hiss) < 0.1);
+    }
+
+    #[test]
+    fn presence_boosts_around_its_frequency() {
+        let on = Bypass::new(false);
+        let boost = |hz: f32| {
+            let s = sine(hz, 8000);
+            let filtered = Biquad::peaking(source(&s), PRESENCE_FREQ, 6.0, 1.0, on.clone());
+            gain(&s, filtered)
+        };
+        assert!((boost(PRESENCE_FREQ) - 2.0).abs() < 0.05);
+        assert!((boost(200.0) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn processing_lines_up_with_the_input() {
+        let samples: Vec<i16> = noise(4000).iter().map(|s| (s * 10_000.0) as i16).collect();
+        let pcm = Pcm {
+            channels: 2,
+            sample_rate: RATE,
+            samples,
+        };
+        let filters = Filters::default();
+        // denoise on, but with no profile there's nothing to take away
+        filters.denoise.set(false);
+        let out = filters.process(&pcm);
+        assert_eq!(out.samples.len(), pcm.samples.len());
+        for (a, b) in out.samples.iter().zip(&pcm.samples) {
+            assert!((a - b).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn bypassed_filters_change_nothing() {
+        let s = noise(1000);
+        let off = Bypass::new(true);
+        let out: Vec<f32> = Biquad::high_pass(source(&s), 1000.0, off.clone()).collect();
+        assert_eq!(out, s);
+        // denoise still delays by half a frame, but otherwise leaves it alone
+        let profile = NoiseProfile::default();
+        profile.set(noise_profile(&s));
+        let out: Vec<f32> = Denoise::new(source(&s), profile, off).collect();
+        for (a, b) in out[HOP..].iter().zip(&s) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn denoise_takes_out_the_noise() {
+        let hum = noise(16_000);
+        let profile = NoiseProfile::default();
+        let on = Bypass::new(false);
+        // nothing to take out until there's a profile
+        let out: Vec<f32> = Denoise::new(source(&hum), profile.clone(), on.clone()).collect();
+        assert!(out.len() >= hum.len() + HOP);
+        assert!((rms(&out[HOP..hum.len()]) / rms(&hum) - 1.0).abs() < 0.01);
+
+        profile.set(noise_profile(&hum));
+        let out: Vec<f32> = Denoise::new(source(&hum), profile.clone(), on.clone()).collect();
+        assert!(rms(&out[HOP..hum.len()]) < rms(&hum) * 0.2);
+
+        let speech: Vec<f32> = sine(500.0, 16_000)
+            .iter()
+            .zip(&hum)
+            .map(|(s, n)| s + n)
+            .collect();
+        let out: Vec<f32> = Denoise::new(source(&speech), profile, on).collect();
+        assert!(rms(&out[HOP..speech.len()]) > rms(&speech) * 0.9);
+    }
+}
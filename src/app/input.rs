@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+
+use super::actions::Action;
+
+/// A button on something other than the keyboard: headset media keys, foot pedals,
+/// gamepads.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub enum Button {
+    PlayPause,
+    Next,
+    Previous,
+    Stop,
+    /// Anything else, by the code its source reports.
+    Other(u16),
+}
+
+impl Button {
+    pub fn name(self) -> String {
+        match self {
+            Button::PlayPause => "Media play/pause".to_owned(),
+            Button::Next => "Media next".to_owned(),
+            Button::Previous => "Media previous".to_owned(),
+            Button::Stop => "Media stop".to_owned(),
+            Button::Other(code) => format!("Button {code}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonEvent {
+    pub button: Button,
+    pub pressed: bool,
+}
+
+/// Somewhere button presses come from. Polled once a frame, so it shouldn't block;
+/// sources that wait for events on a thread should wake the UI when one arrives.
+pub trait InputSource {
+    fn poll(&mut self) -> Vec<ButtonEvent>;
+    /// A line for the settings window.
+    fn status(&self) -> String;
+}
+
+/// Which button triggers each action, like [`super::shortcuts::Keymap`] for keys.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ButtonMap {
+    pub bindings: BTreeMap<Action, Button>,
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        let bindings = [
+            (Action::PlayPause, Button::PlayPause),
+            (Action::NextMark, Button::Next),
+            (Action::PrevMark, Button::Previous),
+        ];
+        Self {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl ButtonMap {
+    /// Binds `action` to `button`, unbinding and returning any action that had it.
+    pub fn bind(&mut self, action: Action, button: Button) -> Option<Action> {
+        let displaced = self
+            .bindings
+            .iter()
+            .find(|(a, b)| **a != action && **b == button)
+            .map(|(a, _)| *a);
+        if let Some(other) = displaced {
+            self.bindings.remove(&other);
+        }
+        self.bindings.insert(action, button);
+        displaced
+    }
+
+    /// The actions triggered by `events`. Only presses count.
+    pub fn actions(&self, events: &[ButtonEvent]) -> Vec<Action> {
+        events
+            .iter()
+            .filter(|e| e.pressed)
+            .filter_map(|e| {
+                self.bindings
+                    .iter()
+                    .find(|(_, b)| **b == e.button)
+                    .map(|(a, _)| *a)
+            })
+            .collect()
+    }
+}
+
+/// All the input sources, opened the first time they're polled.
+#[derive(Default)]
+pub struct Inputs {
+    sources: Option<Vec<Box<dyn InputSource>>>,
+}
+
+impl Inputs {
+    #[cfg(test)]
+    pub fn new(sources: Vec<Box<dyn InputSource>>) -> Self {
+        Self {
+            sources: Some(sources),
+        }
+    }
+
+    /// Button events since the last poll. Some sources see presses meant for other
+    /// windows, so while ours isn't focused they're thrown away.
+    pub fn poll(&mut self, ctx: &egui::Context) -> Vec<ButtonEvent> {
+        let events: Vec<_> = self
+            .sources
+            .get_or_insert_with(|| default_sources(ctx))
+            .iter_mut()
+            .flat_map(|s| s.poll())
+            .collect();
+        if ctx.input().raw.has_focus {
+            events
+        } else {
+            vec![]
+        }
+    }
+
+    pub fn status(&self) -> Vec<String> {
+        match self.sources.as_ref() {
+            Some(sources) if !sources.is_empty() => sources.iter().map(|s| s.status()).collect(),
+            _ => vec!["no button sources on this platform".to_owned()],
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn default_sources(ctx: &egui::Context) -> Vec<Box<dyn InputSource>> {
+    vec![Box::new(evdev::EvdevSource::open(ctx.clone()))]
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_sources(_ctx: &egui::Context) -> Vec<Box<dyn InputSource>> {
+    vec![]
+}
+
+/// Reads the kernel's input devices directly, which sees media keys and pedals whether
+/// or not the window has focus, so [`Inputs::poll`] checks. Needs read access to
+/// `/dev/input`, usually by being in the `input` group.
+#[cfg(target_os = "linux")]
+mod evdev {
+    use std::io::Read;
+    use std::sync::mpsc;
+
+    use super::{Button, ButtonEvent, InputSource};
+
+    /// `struct input_event`: a timestamp, whose size depends on the platform, then
+    /// type, code and value.
+    const EVENT_SIZE: usize = std::mem::size_of::<libc::input_event>();
+    const EV_KEY: u16 = 1;
+
+    /// Media keys and the button ranges used by pedals, joysticks and gamepads. Ordinary
+    /// keys already reach us through the window; mouse and touch buttons are left out.
+    fn button(code: u16) -> Option<Button> {
+        match code {
+            164 => Some(Button::PlayPause),
+            163 => Some(Button::Next),
+            165 => Some(Button::Previous),
+            166 => Some(Button::Stop),
+            0x100..=0x10f | 0x120..=0x13f | 0x2c0..=0x2ff => Some(Button::Other(code)),
+            _ => None,
+        }
+    }
+
+    pub struct EvdevSource {
+        rx: mpsc::Receiver<ButtonEvent>,
+        devices: usize,
+    }
+
+    impl EvdevSource {
+        /// Starts a thread per readable device, waking `ctx` on every button event.
+        pub fn open(ctx: egui::Context) -> Self {
+            let (tx, rx) = mpsc::channel();
+            let paths = std::fs::read_dir("/dev/input")
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.to_string_lossy().contains("/event"));
+            let mut devices = 0;
+            for path in paths {
+                let Ok(mut file) = std::fs::File::open(&path) else {
+                    continue;
+                };
+                devices += 1;
+                let (tx, ctx) = (tx.clone(), ctx.clone());
+                std::thread::spawn(move || {
+                    let mut event = [0; EVENT_SIZE];
+                    // ends when the device is unplugged
+                    while file.read_exact(&mut event).is_ok() {
+                        let at = EVENT_SIZE - 8;
+                        let kind = u16::from_ne_bytes([event[at], event[at + 1]]);
+                        let code = u16::from_ne_bytes([event[at + 2], event[at + 3]]);
+                        let value = i32::from_ne_bytes(event[at + 4..].try_into().unwrap());
+                        // value 2 is key repeat
+                        let Some(button) = button(code).filter(|_| kind == EV_KEY && value < 2)
+                        else {
+                            continue;
+                        };
+                        let pressed = value == 1;
+                        if tx.send(ButtonEvent { button, pressed }).is_err() {
+                            return;
+                        }
+                        ctx.request_repaint();
+                    }
+                });
+            }
+            Self { rx, devices }
+        }
+    }
+
+    impl InputSource for EvdevSource {
+        fn poll(&mut self) -> Vec<ButtonEvent> {
+            self.rx.try_iter().collect()
+        }
+
+        fn status(&self) -> String {
+            match self.devices {
+                0 => "no readable devices in /dev/input (try joining the input group)".to_owned(),
+                n => format!("listening to {n} input devices"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    /// Emits whatever is sent to it.
+    struct FakeSource(mpsc::Receiver<ButtonEvent>);
+
+    impl InputSource for FakeSource {
+        fn poll(&mut self) -> Vec<ButtonEvent> {
+            self.0.try_iter().collect()
+        }
+
+        fn status(&self) -> String {
+            "fake".to_owned()
+        }
+    }
+
+    fn fake() -> (Inputs, mpsc::Sender<ButtonEvent>) {
+        let (tx, rx) = mpsc::channel();
+        (Inputs::new(vec![Box::new(FakeSource(rx))]), tx)
+    }
+
+    fn press(button: Button) -> ButtonEvent {
+        ButtonEvent {
+            button,
+            pressed: true,
+        }
+    }
+
+    #[test]
+    fn presses_trigger_bound_actions() {
+        let (mut inputs, tx) = fake();
+        let map = ButtonMap::default();
+        let ctx = egui::Context::default();
+        tx.send(press(Button::PlayPause)).unwrap();
+        tx.send(press(Button::Next)).unwrap();
+        let actions = map.actions(&inputs.poll(&ctx));
+        assert_eq!(actions, vec![Action::PlayPause, Action::NextMark]);
+        assert!(inputs.poll(&ctx).is_empty());
+    }
+
+    #[test]
+    fn releases_and_unbound_buttons_do_nothing() {
+        let (mut inputs, tx) = fake();
+        let map = ButtonMap::default();
+        let ctx = egui::Context::default();
+        tx.send(ButtonEvent {
+            button: Button::PlayPause,
+            pressed: false,
+        })
+        .unwrap();
+        tx.send(press(Button::Other(0x130))).unwrap();
+        assert!(map.actions(&inputs.poll(&ctx)).is_empty());
+    }
+
+    #[test]
+    fn binding_a_pedal_takes_it_from_other_actions() {
+        let (mut inputs, tx) = fake();
+        let mut map = ButtonMap::default();
+        let ctx = egui::Context::default();
+        let pedal = Button::Other(0x101);
+        assert_eq!(map.bind(Action::ReplaySegment, pedal), None);
+        assert_eq!(map.bind(Action::Mark, pedal), Some(Action::ReplaySegment));
+        tx.send(press(pedal)).unwrap();
+        assert_eq!(map.actions(&inputs.poll(&ctx)), vec![Action::Mark]);
+    }
+}
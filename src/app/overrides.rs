@@ -0,0 +1,338 @@
+use std::time::Duration;
+
+use super::TemplateApp;
+use crate::marks::{self, Mark, Overrides};
+
+/// What segments without their own overrides get. Speed is the side panel slider.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct SegmentDefaults {
+    pub repeats: u32,
+    pub gain_db: f32,
+    pub gap: Duration,
+}
+
+impl Default for SegmentDefaults {
+    fn default() -> Self {
+        Self {
+            repeats: 1,
+            gain_db: 0.0,
+            gap: Duration::ZERO,
+        }
+    }
+}
+
+/// A segment being played more than once, by looping `region` until the player has
+/// gone round `left` more times. After that it's kept until the playhead leaves the
+/// region, so running into the mark again out of the pre-roll doesn't start it over.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Repeating {
+    ind: usize,
+    region: (f32, f32),
+    base: usize,
+    left: usize,
+}
+
+/// What to do with the player's loop for the repeats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopChange {
+    Start((f32, f32)),
+    Stop,
+}
+
+/// A mark playback has just run into, with its segment's padded region and how many
+/// times the segment should play.
+#[derive(Clone, Copy, Debug)]
+pub struct Crossing {
+    pub ind: usize,
+    pub region: (f32, f32),
+    pub repeats: u32,
+}
+
+/// Moves the repeats on by a frame. `looping` and `loops` are the player's loop and
+/// how many times it's gone round, `pos` is the playhead.
+pub fn step_repeats(
+    repeating: &mut Option<Repeating>,
+    crossed: Option<Crossing>,
+    pos: f32,
+    looping: bool,
+    loops: usize,
+) -> Option<LoopChange> {
+    let mut change = None;
+    match repeating {
+        Some(r) if r.left > 0 => {
+            if !looping {
+                // stopped by hand
+                *repeating = None;
+            } else if loops - r.base >= r.left {
+                // on the last time through, let it play on
+                r.left = 0;
+                change = Some(LoopChange::Stop);
+            }
+        }
+        Some(r) if pos < r.region.0 || pos >= r.region.1 => *repeating = None,
+        _ => {}
+    }
+    let looping = looping && change.is_none();
+    let Some(crossed) = crossed else {
+        return change;
+    };
+    let again = repeating.is_some_and(|r| r.ind == crossed.ind);
+    if crossed.repeats > 1 && !looping && !again {
+        *repeating = Some(Repeating {
+            ind: crossed.ind,
+            region: crossed.region,
+            base: loops,
+            left: crossed.repeats as usize - 1,
+        });
+        change = Some(LoopChange::Start(crossed.region));
+    }
+    change
+}
+
+impl TemplateApp {
+    /// Forgets the segment being repeated and stops its loop, for when the marks change
+    /// under it.
+    pub(super) fn stop_repeating(&mut self) {
+        if let Some(repeating) = self.repeating.take() {
+            if repeating.left > 0 {
+                self.audio.set_loop(None);
+            }
+        }
+    }
+
+    /// Plays the segment under the playhead with its overrides, or the one being
+    /// repeated through its pre-roll. Repeats only start when playback runs into a
+    /// segment at mark `crossed`; jumping into the middle of one just plays on. Its gap
+    /// comes each time round, and once on leaving it.
+    pub(super) fn follow_segment(&mut self, crossed: Option<usize>) {
+        let crossed = crossed.map(|ind| Crossing {
+            ind,
+            region: self.padded((
+                self.marks[ind].pos,
+                self.marks.get(ind + 1).map_or(1.0, |m| m.pos),
+            )),
+            repeats: self.marks[ind]
+                .overrides
+                .repeats
+                .unwrap_or(self.segment_defaults.repeats),
+        });
+        let change = step_repeats(
+            &mut self.repeating,
+            crossed,
+            self.cur_pos,
+            self.audio.loop_region().is_some(),
+            self.audio.loops(),
+        );
+        match change {
+            Some(LoopChange::Start(region)) => self.audio.set_loop(Some(region)),
+            Some(LoopChange::Stop) => self.audio.set_loop(None),
+            None => {}
+        }
+
+        let ind = current_segment(&self.marks, &mut self.repeating, self.cur_pos);
+        let overrides = ind
+            .and_then(|i| self.marks.get(i))
+            .map_or_else(Overrides::default, |m| m.overrides.clone());
+        let defaults = &self.segment_defaults;
+        // pause expansion keeps the speech at the speed it's been set to
+        let speed = overrides
+            .speed
+            .filter(|_| !self.pause_settings.enabled)
+            .unwrap_or(self.playback_speed);
+        let gain = marks::gain(overrides.gain_db.unwrap_or(defaults.gain_db));
+        let gap = overrides.gap.unwrap_or(defaults.gap);
+        // going round a loop has its own gap
+        let end = self.marks.get(ind.map_or(0, |i| i + 1)).map(|m| m.pos);
+        let leaving = end.filter(|_| !gap.is_zero() && self.audio.loop_region().is_none());
+        self.audio.set_segment_gap(leaving.map(|end| (end, gap)));
+        if let Some(source) = self.audio.source.as_mut() {
+            if source.state.speed != speed {
+                source.set_speed(speed);
+            }
+            if source.sink.volume() != gain {
+                source.sink.set_volume(gain);
+            }
+            source.set_gap(gap);
+        }
+    }
+}
+
+/// The segment whose overrides apply: the one being repeated, through its pre-roll,
+/// or else the one under `pos`. A repeat whose mark has gone is dropped.
+pub fn current_segment(
+    marks: &[Mark],
+    repeating: &mut Option<Repeating>,
+    pos: f32,
+) -> Option<usize> {
+    if repeating.is_some_and(|r| r.ind >= marks.len()) {
+        *repeating = None;
+    }
+    repeating
+        .map(|r| r.ind)
+        .or_else(|| marks.partition_point(|m| m.pos <= pos).checked_sub(1))
+}
+
+pub fn defaults_ui(ui: &mut egui::Ui, defaults: &mut SegmentDefaults) {
+    egui::Grid::new("segment_defaults_grid").show(ui, |ui| {
+        ui.label("Repeats");
+        ui.add(egui::DragValue::new(&mut defaults.repeats).clamp_range(1..=10));
+        ui.end_row();
+        ui.label("Volume");
+        ui.add(
+            egui::DragValue::new(&mut defaults.gain_db)
+                .clamp_range(-20.0..=20.0)
+                .suffix(" dB"),
+        );
+        ui.end_row();
+        ui.label("Gap");
+        let mut secs = defaults.gap.as_secs_f32();
+        ui.add(
+            egui::DragValue::new(&mut secs)
+                .speed(0.05)
+                .clamp_range(0.0..=10.0)
+                .suffix(" s"),
+        );
+        defaults.gap = Duration::from_secs_f32(secs);
+        ui.end_row();
+    });
+}
+
+/// Checkboxes to override each setting for one segment, starting from the global value.
+pub fn overrides_ui(
+    ui: &mut egui::Ui,
+    overrides: &mut Overrides,
+    speed: f32,
+    defaults: &SegmentDefaults,
+) {
+    egui::Grid::new("overrides_grid").show(ui, |ui| {
+        override_row(ui, "Speed", &mut overrides.speed, speed, |v| {
+            egui::DragValue::new(v)
+                .speed(0.01)
+                .clamp_range(0.5..=3.0)
+                .suffix("x")
+        });
+        override_row(
+            ui,
+            "Repeats",
+            &mut overrides.repeats,
+            defaults.repeats,
+            |v| egui::DragValue::new(v).clamp_range(1..=10),
+        );
+        override_row(
+            ui,
+            "Volume",
+            &mut overrides.gain_db,
+            defaults.gain_db,
+            |v| {
+                egui::DragValue::new(v)
+                    .clamp_range(-20.0..=20.0)
+                    .suffix(" dB")
+            },
+        );
+        let mut gap = overrides.gap.map(|g| g.as_secs_f32());
+        override_row(ui, "Gap", &mut gap, defaults.gap.as_secs_f32(), |v| {
+            egui::DragValue::new(v)
+                .speed(0.05)
+                .clamp_range(0.0..=10.0)
+                .suffix(" s")
+        });
+        overrides.gap = gap.map(Duration::from_secs_f32);
+    });
+}
+
+fn override_row<T: Copy>(
+    ui: &mut egui::Ui,
+    name: &str,
+    value: &mut Option<T>,
+    default: T,
+    edit: impl FnOnce(&mut T) -> egui::DragValue<'_>,
+) {
+    let mut set = value.is_some();
+    if ui.checkbox(&mut set, name).changed() {
+        *value = set.then_some(default);
+    }
+    match value {
+        Some(v) => ui.add(edit(v)),
+        None => ui.weak("default"),
+    };
+    ui.end_row();
+}
+
+/// The overrides as small labels.
+pub fn badges_ui(ui: &mut egui::Ui, overrides: &Overrides) {
+    for badge in overrides.badges() {
+        ui.label(
+            egui::RichText::new(badge)
+                .small()
+                .background_color(ui.visuals().faint_bg_color),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGION: (f32, f32) = (0.25, 0.5);
+
+    fn crossing(repeats: u32) -> Option<Crossing> {
+        Some(Crossing {
+            ind: 1,
+            region: REGION,
+            repeats,
+        })
+    }
+
+    #[test]
+    fn repeats_then_plays_on() {
+        let mut repeating = None;
+        let change = step_repeats(&mut repeating, crossing(3), 0.3, false, 7);
+        assert_eq!(change, Some(LoopChange::Start(REGION)));
+        assert_eq!(step_repeats(&mut repeating, None, 0.45, true, 7), None);
+        assert_eq!(step_repeats(&mut repeating, None, 0.26, true, 8), None);
+        // back round into the pre-roll for the last time
+        let change = step_repeats(&mut repeating, None, 0.26, true, 9);
+        assert_eq!(change, Some(LoopChange::Stop));
+        // running into the mark out of the pre-roll again doesn't start it over
+        assert_eq!(
+            step_repeats(&mut repeating, crossing(3), 0.3, false, 9),
+            None
+        );
+        assert_eq!(step_repeats(&mut repeating, None, 0.45, false, 9), None);
+        // but it does once playback has left the segment
+        assert_eq!(step_repeats(&mut repeating, None, 0.6, false, 9), None);
+        assert_eq!(repeating, None);
+        let change = step_repeats(&mut repeating, crossing(3), 0.3, false, 9);
+        assert_eq!(change, Some(LoopChange::Start(REGION)));
+    }
+
+    #[test]
+    fn repeats_of_deleted_marks_are_dropped() {
+        let marks = [Mark::new(0.1), Mark::new(0.25), Mark::new(0.5)];
+        let mut repeating = None;
+        step_repeats(&mut repeating, crossing(3), 0.3, false, 0);
+        // still the repeated segment while in its pre-roll
+        assert_eq!(current_segment(&marks, &mut repeating, 0.2), Some(1));
+        assert_eq!(current_segment(&marks[..1], &mut repeating, 0.3), Some(0));
+        assert_eq!(repeating, None);
+    }
+
+    #[test]
+    fn single_plays_and_loops_by_hand_are_left_alone() {
+        let mut repeating = None;
+        assert_eq!(
+            step_repeats(&mut repeating, crossing(1), 0.3, false, 0),
+            None
+        );
+        assert_eq!(
+            step_repeats(&mut repeating, crossing(3), 0.3, true, 0),
+            None
+        );
+        assert_eq!(repeating, None);
+        step_repeats(&mut repeating, crossing(3), 0.3, false, 0);
+        // stopped by hand
+        assert_eq!(step_repeats(&mut repeating, None, 0.35, false, 0), None);
+        assert_eq!(repeating, None);
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use super::TemplateApp;
+use crate::analysis::{self, Expansion, SilenceParams};
+
+/// Lengthening the pauses between phrases, instead of slowing the phrases down.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct PauseSettings {
+    pub enabled: bool,
+    pub expansion: Expansion,
+    /// Shorter quiet stretches are part of the phrase, not a pause.
+    pub min_pause: Duration,
+}
+
+impl Default for PauseSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            expansion: Expansion::Stretch(2.0),
+            min_pause: Duration::from_millis(150),
+        }
+    }
+}
+
+/// The phrases of the loaded file, found in the background since it means going over
+/// the whole of it.
+#[derive(Default)]
+pub struct PauseFinder {
+    /// What the phrases are for: the load of the file, see
+    /// [`AudioPlayer::load_id`](super::audio::AudioPlayer::load_id), and the minimum
+    /// pause.
+    key: Option<(usize, Duration)>,
+    rx: Option<mpsc::Receiver<Vec<(Duration, Duration)>>>,
+    phrases: Option<Vec<(Duration, Duration)>>,
+    /// What the player was last given.
+    sent: Option<Expansion>,
+}
+
+impl TemplateApp {
+    /// Keeps the player's pauses in line with the settings and the loaded file.
+    pub(super) fn follow_pauses(&mut self, ctx: &egui::Context) {
+        let finder = &mut self.pause_finder;
+        let settings = &self.pause_settings;
+        let (Some(pcm), true) = (self.audio.pcm(), settings.enabled) else {
+            if finder.sent.take().is_some() {
+                self.audio.set_pauses(&[]);
+            }
+            return;
+        };
+
+        let key = (self.audio.load_id(), settings.min_pause);
+        if finder.key.as_ref() != Some(&key) {
+            let params = SilenceParams {
+                min_silence: settings.min_pause,
+                ..Default::default()
+            };
+            let (tx, rx) = mpsc::channel();
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                let phrases = analysis::detect_segments(&pcm.mono(), pcm.sample_rate, &params);
+                _ = tx.send(phrases);
+                ctx.request_repaint();
+            });
+            *finder = PauseFinder {
+                key: Some(key),
+                rx: Some(rx),
+                phrases: None,
+                sent: finder.sent,
+            };
+        }
+        if let Some(phrases) = finder.rx.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            finder.rx = None;
+            finder.phrases = Some(phrases);
+            finder.sent = None;
+        }
+        let Some(phrases) = finder.phrases.as_ref() else {
+            return;
+        };
+        if finder.sent != Some(settings.expansion) {
+            finder.sent = Some(settings.expansion);
+            let pauses = analysis::expanded_pauses(phrases, settings.expansion);
+            self.audio.set_pauses(&pauses);
+        }
+    }
+}
+
+pub fn pauses_ui(ui: &mut egui::Ui, settings: &mut PauseSettings, finder: &PauseFinder) -> bool {
+    let enabled = ui
+        .checkbox(&mut settings.enabled, "Lengthen pauses")
+        .on_hover_text("gives you time to echo while the speech itself stays at normal speed")
+        .changed()
+        && settings.enabled;
+    ui.add_enabled_ui(settings.enabled, |ui| {
+        let expansion = &mut settings.expansion;
+        ui.horizontal(|ui| {
+            if ui
+                .radio(matches!(expansion, Expansion::Stretch(_)), "Stretch")
+                .clicked()
+            {
+                *expansion = Expansion::Stretch(2.0);
+            }
+            if ui
+                .radio(matches!(expansion, Expansion::Echo(_)), "Echo")
+                .clicked()
+            {
+                *expansion = Expansion::Echo(1.0);
+            }
+        });
+        match expansion {
+            Expansion::Stretch(factor) => ui.add(
+                egui::DragValue::new(factor)
+                    .speed(0.05)
+                    .clamp_range(1.0..=10.0)
+                    .prefix("pauses ×"),
+            ),
+            Expansion::Echo(ratio) => ui
+                .add(
+                    egui::DragValue::new(ratio)
+                        .speed(0.05)
+                        .clamp_range(0.0..=3.0)
+                        .prefix("+")
+                        .suffix(" s per s of speech"),
+                )
+                .on_hover_text("extra silence after each phrase, for its length"),
+        };
+        let mut ms = settings.min_pause.as_millis() as u64;
+        ui.horizontal(|ui| {
+            ui.label("Shortest pause");
+            ui.add(
+                egui::DragValue::new(&mut ms)
+                    .clamp_range(50..=1000)
+                    .suffix(" ms"),
+            );
+        });
+        settings.min_pause = Duration::from_millis(ms);
+        match (&finder.phrases, &finder.rx) {
+            (_, Some(_)) => ui.weak("finding pauses…"),
+            (Some(phrases), None) => ui.weak(format!("{} pauses", phrases.len().saturating_sub(1))),
+            _ => ui.weak(""),
+        };
+    });
+    enabled
+}
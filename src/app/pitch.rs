@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use super::audio::Pcm;
+use crate::analysis::{self, PitchParams};
+
+/// Coarser than the compare window: this runs over whole files.
+const PARAMS: PitchParams = PitchParams {
+    min_hz: 60.0,
+    max_hz: 500.0,
+    threshold: 0.15,
+    silence_db: -40.0,
+    hop: Duration::from_millis(20),
+    max_rate: 8_000,
+};
+
+/// A whole file's F0, scaled for drawing.
+pub struct Contour {
+    /// 0 at the bottom of the speaker's range to 1 at the top, `None` where unvoiced.
+    pub heights: Vec<Option<f32>>,
+    pub hop: Duration,
+}
+
+impl Contour {
+    fn new(f0: &[Option<f32>], hop: Duration) -> Self {
+        let mut octaves: Vec<f32> = f0.iter().flatten().map(|f| f.log2()).collect();
+        octaves.sort_by(f32::total_cmp);
+        let percentile = |p: f32| {
+            let ind = ((octaves.len() as f32 * p) as usize).min(octaves.len().saturating_sub(1));
+            octaves.get(ind).copied().unwrap_or(0.0)
+        };
+        // a couple of semitones of headroom either side of the usual range
+        let (lo, hi) = (percentile(0.05) - 2.0 / 12.0, percentile(0.95) + 2.0 / 12.0);
+        let heights = f0
+            .iter()
+            .map(|f| f.map(|f| (f.log2() - lo) / (hi - lo).max(f32::EPSILON)))
+            .collect();
+        Self { heights, hop }
+    }
+}
+
+/// Contours of the files opened so far. Each is worked out once, on a background thread.
+#[derive(Default)]
+pub struct PitchCache {
+    contours: HashMap<String, Contour>,
+    pending: Option<(String, mpsc::Receiver<Contour>)>,
+}
+
+impl PitchCache {
+    /// Picks up finished work, and starts working out the contour of `path` from `pcm`
+    /// if it isn't known yet and nothing else is in progress.
+    pub fn request(&mut self, path: &str, pcm: impl FnOnce() -> Option<Arc<Pcm>>) {
+        if let Some((pending, rx)) = self.pending.as_ref() {
+            match rx.try_recv() {
+                Ok(contour) => {
+                    self.contours.insert(pending.clone(), contour);
+                    self.pending = None;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => self.pending = None,
+                Err(mpsc::TryRecvError::Empty) => {}
+            }
+        }
+        if !self.contours.contains_key(path) && self.pending.is_none() {
+            let Some(pcm) = pcm() else {
+                return;
+            };
+            let (tx, rx) = mpsc::channel();
+            std::thread::spawn(move || {
+                let f0 = analysis::pitch_track(&pcm.mono(), pcm.sample_rate, &PARAMS);
+                _ = tx.send(Contour::new(&f0, PARAMS.hop));
+            });
+            self.pending = Some((path.to_owned(), rx));
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Contour> {
+        self.contours.get(path)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
@@ -0,0 +1,152 @@
+use super::TemplateApp;
+
+/// Speeds a looped segment up (or down) as it repeats: `repeats` times at `start`,
+/// then `step` faster each time round until `end`, where it stays.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct SpeedRamp {
+    pub enabled: bool,
+    pub start: f32,
+    pub end: f32,
+    pub step: f32,
+    pub repeats: usize,
+}
+
+impl Default for SpeedRamp {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: 0.7,
+            end: 1.0,
+            step: 0.1,
+            repeats: 1,
+        }
+    }
+}
+
+impl SpeedRamp {
+    /// The speed for the `rep`th time through, counting from 0.
+    pub fn speed_at(&self, rep: usize) -> f32 {
+        let steps = (rep / self.repeats.max(1)) as f32;
+        let speed = if self.end >= self.start {
+            (self.start + steps * self.step.abs()).min(self.end)
+        } else {
+            (self.start - steps * self.step.abs()).max(self.end)
+        };
+        // steps of 0.1 add up to 0.7999999
+        (speed * 1000.0).round() / 1000.0
+    }
+}
+
+/// Where a ramped loop has got to.
+#[derive(Clone, Copy, Debug)]
+pub struct RampProgress {
+    /// The player's loop count when the loop started.
+    base: usize,
+    pub rep: usize,
+}
+
+impl TemplateApp {
+    /// Starts ramping the loop just set, if the ramp is on. Pause expansion keeps the
+    /// speech at its own speed, so there's no ramp while it's on.
+    pub(super) fn start_ramp(&mut self) {
+        self.ramp_progress = None;
+        let ramping = self.speed_ramp.enabled && !self.pause_settings.enabled;
+        if ramping && self.audio.loop_region().is_some() {
+            self.ramp_progress = Some(RampProgress {
+                base: self.audio.loops(),
+                rep: 0,
+            });
+            self.set_speed(self.speed_ramp.speed_at(0));
+        }
+    }
+
+    /// Moves the speed on each time the loop comes round. In between, the slider is left
+    /// alone so it can still be changed by hand.
+    pub(super) fn follow_ramp(&mut self) {
+        let Some(progress) = self.ramp_progress.as_mut() else {
+            return;
+        };
+        if self.audio.loop_region().is_none() || self.pause_settings.enabled {
+            self.ramp_progress = None;
+            return;
+        }
+        let rep = self.audio.loops() - progress.base;
+        if rep != progress.rep {
+            progress.rep = rep;
+            self.set_speed(self.speed_ramp.speed_at(rep));
+        }
+    }
+}
+
+fn speed(value: &mut f32) -> egui::DragValue<'_> {
+    egui::DragValue::new(value)
+        .speed(0.01)
+        .clamp_range(0.5..=3.0)
+        .suffix("x")
+}
+
+pub fn ramp_ui(ui: &mut egui::Ui, ramp: &mut SpeedRamp, progress: Option<RampProgress>) {
+    ui.checkbox(&mut ramp.enabled, "Ramp speed while looping");
+    ui.add_enabled_ui(ramp.enabled, |ui| {
+        egui::Grid::new("ramp_grid").show(ui, |ui| {
+            ui.label("From");
+            ui.add(speed(&mut ramp.start));
+            ui.end_row();
+            ui.label("To");
+            ui.add(speed(&mut ramp.end));
+            ui.end_row();
+            ui.label("Step");
+            ui.add(
+                egui::DragValue::new(&mut ramp.step)
+                    .speed(0.01)
+                    .clamp_range(0.01..=1.0),
+            );
+            ui.end_row();
+            ui.label("Repeats per step");
+            ui.add(egui::DragValue::new(&mut ramp.repeats).clamp_range(1..=20));
+            ui.end_row();
+        });
+    });
+    if let Some(progress) = progress {
+        ui.label(format!(
+            "repetition {} at {:.2}x",
+            progress.rep + 1,
+            ramp.speed_at(progress.rep)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_up_and_holds() {
+        let ramp = SpeedRamp {
+            enabled: true,
+            start: 0.7,
+            end: 1.0,
+            step: 0.1,
+            repeats: 2,
+        };
+        let speeds: Vec<f32> = (0..10).map(|rep| ramp.speed_at(rep)).collect();
+        assert_eq!(
+            speeds,
+            vec![0.7, 0.7, 0.8, 0.8, 0.9, 0.9, 1.0, 1.0, 1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn ramps_down_without_overshooting() {
+        let ramp = SpeedRamp {
+            enabled: true,
+            start: 1.2,
+            end: 0.95,
+            step: 0.1,
+            repeats: 1,
+        };
+        let speeds: Vec<f32> = (0..5).map(|rep| ramp.speed_at(rep)).collect();
+        assert_eq!(speeds, vec![1.2, 1.1, 1.0, 0.95, 0.95]);
+    }
+}
@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::cpal::{self, SampleFormat};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::audio::Pcm;
+
+/// Records from the default input device until [`Recorder::finish`] is called.
+pub struct Recorder {
+    stream: cpal::Stream,
+    samples: Arc<Mutex<Vec<i16>>>,
+    /// What went wrong with the stream, reported from the audio thread.
+    error: Arc<Mutex<Option<String>>>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Recorder {
+    pub fn start() -> Result<Self> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .context("no input device")?;
+        let supported = device
+            .default_input_config()
+            .context("couldn't configure the input device")?;
+        let config = supported.config();
+        let samples = Arc::new(Mutex::new(vec![]));
+        let error = Arc::new(Mutex::new(None));
+        let (s, e) = (samples.clone(), error.clone());
+        let stream = match supported.sample_format() {
+            SampleFormat::I16 => input_stream::<i16>(&device, &config, s, e),
+            SampleFormat::U16 => input_stream::<u16>(&device, &config, s, e),
+            SampleFormat::F32 => input_stream::<f32>(&device, &config, s, e),
+        }
+        .context("couldn't open the input device")?;
+        stream.play().context("couldn't start recording")?;
+        Ok(Self {
+            stream,
+            samples,
+            error,
+            channels: config.channels,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        let len = self.samples.lock().map(|s| s.len()).unwrap_or(0);
+        Duration::from_secs_f64(len as f64 / (self.sample_rate as f64 * self.channels as f64))
+    }
+
+    /// Why recording stopped working, if it has.
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().ok().and_then(|e| e.clone())
+    }
+
+    pub fn finish(self) -> Pcm {
+        _ = self.stream.pause();
+        drop(self.stream);
+        let mut pcm = Pcm::new(self.channels, self.sample_rate);
+        if let Ok(mut samples) = self.samples.lock() {
+            pcm.samples = std::mem::take(&mut *samples);
+        }
+        pcm
+    }
+}
+
+fn input_stream<T: cpal::Sample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples: Arc<Mutex<Vec<i16>>>,
+    error: Arc<Mutex<Option<String>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            if let Ok(mut samples) = samples.lock() {
+                samples.extend(data.iter().map(|s| s.to_i16()));
+            }
+        },
+        move |e| {
+            if let Ok(mut error) = error.lock() {
+                *error = Some(format!("recording failed: {e}"));
+            }
+        },
+    )
+}
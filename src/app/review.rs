@@ -0,0 +1,271 @@
+use anyhow::Context;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use super::audio::{self, Pcm, SoundData};
+use super::TemplateApp;
+use crate::marks;
+use crate::review::{self, Grade};
+use crate::stats::Event;
+
+/// Longest pause after a clip that still counts as shadowing it, rather than a break.
+const MAX_GAP: f64 = 30.0;
+
+/// A run through the segments that were due when the review window was opened.
+#[derive(Default)]
+pub struct ReviewSession {
+    queue: Vec<String>,
+    revealed: bool,
+    /// The last file a segment was played from, decoded, so its next segment starts at once.
+    decoded: Option<(String, Arc<Pcm>)>,
+    /// Another file being decoded in the background: the item to play once it's done,
+    /// and the file.
+    decoding: Option<(String, String, mpsc::Receiver<anyhow::Result<Pcm>>)>,
+    sink: Option<rodio::Sink>,
+    /// The item last played and when (in egui time) its clip finishes.
+    played: Option<(String, f64)>,
+    status: Option<String>,
+}
+
+impl TemplateApp {
+    pub(super) fn start_review(&mut self, now: f64) {
+        self.review.queue = self.reviews.due(review::today());
+        self.review.revealed = false;
+        self.review.status = None;
+        self.review_open = true;
+        if let Some(key) = self.review.queue.first().cloned() {
+            self.play_review_item(&key, now);
+        }
+    }
+
+    /// Starts learning the segment at mark `ind` of the current file.
+    pub(super) fn learn_segment(&mut self, ind: usize) {
+        let (Some(path), Some(total)) = (self.picked_path.as_ref(), self.audio.total_time()) else {
+            return;
+        };
+        if let Some(segment) = marks::segment_at(&self.marks, ind, total) {
+            self.reviews.add(path, &segment, review::today());
+        }
+    }
+
+    /// Starts learning every segment of the current file. Returns how many were new.
+    fn learn_all_segments(&mut self) -> usize {
+        let (Some(path), Some(total)) = (self.picked_path.as_ref(), self.audio.total_time()) else {
+            return 0;
+        };
+        let today = review::today();
+        marks::segments(&self.marks, total)
+            .iter()
+            .filter(|segment| self.reviews.add(path, segment, today))
+            .count()
+    }
+
+    /// Counts the time since the last clip finished as shadowing it.
+    fn record_review_gap(&mut self, now: f64) {
+        let Some((key, ends_at)) = self.review.played.take() else {
+            return;
+        };
+        let Some(item) = self.reviews.items.get(&key) else {
+            return;
+        };
+        let gap = (now - ends_at).clamp(0.0, MAX_GAP);
+        self.stats.record(
+            &item.audio_path,
+            Some(item.start),
+            Event::Gap(std::time::Duration::from_secs_f64(gap)),
+            review::today(),
+        );
+    }
+
+    fn play_review_item(&mut self, key: &str, now: f64) {
+        let replay = self.review.played.as_ref().is_some_and(|(k, _)| k == key);
+        self.record_review_gap(now);
+        let Some(item) = self.reviews.items.get(key) else {
+            return;
+        };
+        let session = &mut self.review;
+        session.sink = None;
+        let loaded = self
+            .audio
+            .pcm()
+            .filter(|_| self.picked_path.as_ref() == Some(&item.audio_path));
+        let pcm = match (session.decoded.take(), loaded) {
+            (_, Some(pcm)) => pcm,
+            (Some((path, pcm)), _) if path == item.audio_path => pcm,
+            _ => {
+                // decoding a whole file takes a while, the clip plays once it's done
+                let path = item.audio_path.clone();
+                match session.decoding.as_mut() {
+                    Some((playing, decoding, _)) if *decoding == path => *playing = key.to_owned(),
+                    _ => session.decoding = Some((key.to_owned(), path.clone(), decode(path))),
+                }
+                session.status = Some("loading…".to_owned());
+                return;
+            }
+        };
+        session.decoding = None;
+        let mut clip = pcm.slice(item.start, item.end);
+        clip.fade_edges(audio::FADE_IN);
+        session.decoded = Some((item.audio_path.clone(), pcm));
+        let len = clip.duration().div_f32(self.playback_speed);
+        match self.audio.play_clip(&clip, self.playback_speed) {
+            Ok(sink) => {
+                session.sink = Some(sink);
+                session.played = Some((key.to_owned(), now + len.as_secs_f64()));
+                session.status = None;
+                let today = review::today();
+                let event = if replay { Event::Loop } else { Event::Play };
+                let segment = Some(item.start);
+                self.stats.record(&item.audio_path, segment, event, today);
+                self.stats
+                    .record(&item.audio_path, segment, Event::Listen(len), today);
+            }
+            Err(e) => session.status = Some(format!("couldn't play segment: {e:#}")),
+        }
+        if self.audio.is_playing() {
+            self.audio.toggle_play();
+        }
+    }
+
+    /// Plays the item waiting on another file once that's decoded.
+    fn follow_review_decode(&mut self, ctx: &egui::Context, now: f64) {
+        let Some((_, _, rx)) = self.review.decoding.as_ref() else {
+            return;
+        };
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(Duration::from_millis(100));
+                return;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => Err(anyhow::anyhow!("decoding stopped")),
+        };
+        let Some((key, path, _)) = self.review.decoding.take() else {
+            return;
+        };
+        match result {
+            Ok(pcm) => {
+                self.review.decoded = Some((path, Arc::new(pcm)));
+                self.review.status = None;
+                self.play_review_item(&key, now);
+            }
+            Err(e) => self.review.status = Some(format!("couldn't play segment: {e:#}")),
+        }
+    }
+
+    fn grade_review_item(&mut self, key: &str, grade: Grade, now: f64) {
+        self.record_review_gap(now);
+        self.reviews.grade(key, grade, review::today());
+        self.review.queue.retain(|k| k != key);
+        // failed segments come back at the end of the session
+        if grade == Grade::Again {
+            self.review.queue.push(key.to_owned());
+        }
+        self.review.revealed = false;
+        if let Some(next) = self.review.queue.first().cloned() {
+            self.play_review_item(&next, now);
+        } else {
+            self.review.sink = None;
+        }
+    }
+
+    pub(super) fn review_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.review_open;
+        let now = ctx.input().time;
+        let mut play = None;
+        let mut graded = None;
+        self.follow_review_decode(ctx, now);
+        egui::Window::new("Review").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} segments learned", self.reviews.items.len()));
+                if ui
+                    .add_enabled(
+                        self.picked_path.is_some(),
+                        egui::Button::new("Learn all segments of this file"),
+                    )
+                    .clicked()
+                {
+                    let added = self.learn_all_segments();
+                    self.review.status = Some(format!("added {added} segments"));
+                }
+            });
+            if let Some(status) = self.review.status.as_ref() {
+                ui.label(status);
+            }
+            ui.separator();
+
+            let Some(key) = self.review.queue.first().cloned() else {
+                ui.label("Nothing left to review today.");
+                if ui.button("Check again").clicked() {
+                    self.review.queue = self.reviews.due(review::today());
+                }
+                return;
+            };
+            let Some(item) = self.reviews.items.get(&key) else {
+                self.review.queue.remove(0);
+                return;
+            };
+            ui.label(format!("{} left", self.review.queue.len()));
+            ui.label(format!(
+                "{} {:.1}s – {:.1}s",
+                item.audio_path,
+                item.start.as_secs_f32(),
+                item.end.as_secs_f32()
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("Play").clicked() {
+                    play = Some(key.clone());
+                }
+                if ui.button("Show").clicked() {
+                    self.review.revealed = true;
+                }
+            });
+            if self.review.revealed {
+                ui.label(&item.label);
+                ui.weak(&item.translation);
+            }
+            ui.horizontal(|ui| {
+                let today = review::today();
+                for grade in Grade::ALL {
+                    let mut card = item.card.clone();
+                    card.review(grade, today);
+                    if ui
+                        .button(grade.name())
+                        .on_hover_text(format!("next review in {} days", card.interval))
+                        .clicked()
+                    {
+                        graded = Some((key.clone(), grade));
+                    }
+                }
+            });
+        });
+
+        if let Some(key) = play {
+            self.play_review_item(&key, now);
+        }
+        if let Some((key, grade)) = graded {
+            self.grade_review_item(&key, grade, now);
+        }
+        if !open && self.review_open {
+            self.record_review_gap(now);
+            self.review.sink = None;
+            self.review.decoding = None;
+        }
+        self.review_open = open;
+    }
+}
+
+/// Reads and decodes `path` on a worker thread.
+fn decode(path: String) -> mpsc::Receiver<anyhow::Result<Pcm>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let pcm = SoundData::new(&path)
+            .with_context(|| format!("couldn't read {path}"))
+            .and_then(|data| {
+                data.decode()
+                    .with_context(|| format!("couldn't decode {path}"))
+            });
+        _ = tx.send(pcm);
+    });
+    rx
+}
@@ -0,0 +1,403 @@
+use egui::{Key, KeyboardShortcut, ModifierNames, Modifiers};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use super::actions::Action;
+use super::capture::Holder;
+use super::TemplateApp;
+
+/// A key plus the modifiers that have to be held with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Binding {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl Binding {
+    pub const fn new(modifiers: Modifiers, key: Key) -> Self {
+        Self { key, modifiers }
+    }
+
+    pub fn name(&self) -> String {
+        KeyboardShortcut::new(self.modifiers, self.key)
+            .format(&ModifierNames::NAMES, cfg!(target_os = "macos"))
+    }
+}
+
+/// Which key triggers each action. Actions without an entry are unbound.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Keymap {
+    pub bindings: BTreeMap<Action, Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let none = Modifiers::NONE;
+        let bindings = [
+            (Action::PlayPause, Binding::new(none, Key::Space)),
+            (Action::Mark, Binding::new(none, Key::M)),
+            (Action::PrevMark, Binding::new(none, Key::ArrowLeft)),
+            (Action::NextMark, Binding::new(none, Key::ArrowRight)),
+            (Action::LoopSegment, Binding::new(none, Key::L)),
+            (Action::SpeedUp, Binding::new(none, Key::PlusEquals)),
+            (Action::SpeedDown, Binding::new(none, Key::Minus)),
+            (Action::ReplaySegment, Binding::new(none, Key::R)),
+            (Action::Record, Binding::new(Modifiers::SHIFT, Key::R)),
+            (Action::Rewind, Binding::new(none, Key::B)),
+            (
+                Action::NudgeBack,
+                Binding::new(Modifiers::SHIFT, Key::ArrowLeft),
+            ),
+            (
+                Action::NudgeForward,
+                Binding::new(Modifiers::SHIFT, Key::ArrowRight),
+            ),
+            (Action::ReplayPhrase, Binding::new(none, Key::P)),
+            (Action::CaptureSegment, Binding::new(none, Key::C)),
+            (Action::Undo, Binding::new(Modifiers::COMMAND, Key::Z)),
+            (
+                Action::Redo,
+                Binding::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z),
+            ),
+        ];
+        Self {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The action other than `action` that `binding` triggers, if any.
+    pub fn bound_to(&self, binding: Binding, action: Action) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(a, b)| **a != action && **b == binding)
+            .map(|(a, _)| *a)
+    }
+
+    /// Binds `action` to `binding`, unbinding and returning any action that had it.
+    pub fn bind(&mut self, action: Action, binding: Binding) -> Option<Action> {
+        let displaced = self.bound_to(binding, action);
+        if let Some(other) = displaced {
+            self.bindings.remove(&other);
+        }
+        self.bindings.insert(action, binding);
+        displaced
+    }
+
+    /// The other actions sharing `action`'s key. Only possible with a hand-edited or
+    /// old saved keymap, [`Keymap::bind`] never leaves any.
+    pub fn conflicts(&self, action: Action) -> Vec<Action> {
+        let Some(binding) = self.bindings.get(&action) else {
+            return vec![];
+        };
+        self.bindings
+            .iter()
+            .filter(|(a, b)| **a != action && *b == binding)
+            .map(|(a, _)| *a)
+            .collect()
+    }
+
+    /// The actions whose keys were pressed this frame. Conflicting bindings don't
+    /// trigger anything.
+    pub fn pressed(&self, input: &mut egui::InputState) -> Vec<Action> {
+        self.bindings
+            .iter()
+            .filter(|(action, _)| self.conflicts(**action).is_empty())
+            .filter(|(_, b)| input.consume_key(b.modifiers, b.key))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+}
+
+/// State of the shortcuts window.
+#[derive(Default)]
+pub struct ShortcutEditor {
+    /// Waiting for a key press to bind to this action.
+    capturing: Option<Action>,
+    /// Waiting for a button press to bind to this action.
+    capturing_button: Option<Action>,
+    /// A key pressed for the first action that's already the second's, waiting for the
+    /// go-ahead to move it.
+    taking: Option<(Action, Binding, Action)>,
+    status: Option<String>,
+}
+
+impl TemplateApp {
+    /// Runs the actions whose shortcuts were pressed, unless a text field has the keyboard.
+    pub(super) fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        self.check_capture_key(ctx);
+        if ctx.wants_keyboard_input() || self.shortcut_editor.capturing.is_some() {
+            return;
+        }
+        let actions = self.keymap.pressed(&mut ctx.input_mut());
+        for action in actions {
+            match self.keymap.bindings.get(&action) {
+                Some(binding) if action == Action::CaptureSegment => {
+                    self.begin_capture(Holder::Key(binding.key))
+                }
+                _ => self.perform(action),
+            }
+        }
+    }
+
+    /// Runs the actions bound to media keys, pedals and gamepad buttons. These work
+    /// even while typing, they don't go through the keyboard.
+    pub(super) fn handle_buttons(&mut self, ctx: &egui::Context) {
+        let events = self.inputs.poll(ctx);
+        if let Some(action) = self.shortcut_editor.capturing_button {
+            let Some(button) = events.iter().find(|e| e.pressed).map(|e| e.button) else {
+                return;
+            };
+            let editor = &mut self.shortcut_editor;
+            editor.capturing_button = None;
+            editor.status = self.buttons.bind(action, button).map(|other| {
+                format!(
+                    "{} was bound to {}, which is now unbound",
+                    button.name(),
+                    other.name()
+                )
+            });
+            return;
+        }
+        if let Some((_, Holder::Button(held))) = self.capture {
+            if events.iter().any(|e| e.button == held && !e.pressed) {
+                self.end_capture();
+            }
+        }
+        for action in self.buttons.actions(&events) {
+            match self.buttons.bindings.get(&action) {
+                Some(button) if action == Action::CaptureSegment => {
+                    self.begin_capture(Holder::Button(*button))
+                }
+                _ => self.perform(action),
+            }
+        }
+    }
+
+    pub(super) fn shortcuts_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.shortcuts_open;
+        if let Some(action) = self.shortcut_editor.capturing {
+            self.capture_binding(ctx, action);
+        }
+        egui::Window::new("Keyboard shortcuts")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let jumps = &mut self.jumps;
+                ui.horizontal(|ui| {
+                    let mut secs = jumps.rewind.as_secs_f32();
+                    ui.label("Go back");
+                    ui.add(
+                        egui::DragValue::new(&mut secs)
+                            .speed(0.1)
+                            .clamp_range(0.1..=30.0)
+                            .suffix(" s"),
+                    );
+                    jumps.rewind = Duration::from_secs_f32(secs);
+                    let mut ms = jumps.nudge.as_millis() as u64;
+                    ui.label("Nudge");
+                    ui.add(
+                        egui::DragValue::new(&mut ms)
+                            .clamp_range(10..=2000)
+                            .suffix(" ms"),
+                    );
+                    jumps.nudge = Duration::from_millis(ms);
+                });
+                let settings = &mut self.mark_settings;
+                ui.horizontal(|ui| {
+                    let mut ms = settings.reaction.as_millis() as u64;
+                    ui.label("Mark reaction time");
+                    ui.add(
+                        egui::DragValue::new(&mut ms)
+                            .clamp_range(0..=1000)
+                            .suffix(" ms"),
+                    )
+                    .on_hover_text("new marks go this far back, more at higher speeds");
+                    settings.reaction = Duration::from_millis(ms);
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut settings.snap, "Snap marks to pauses within");
+                    let mut ms = settings.snap_window.as_millis() as u64;
+                    ui.add_enabled(
+                        settings.snap,
+                        egui::DragValue::new(&mut ms)
+                            .clamp_range(20..=1000)
+                            .suffix(" ms"),
+                    );
+                    settings.snap_window = Duration::from_millis(ms);
+                });
+                ui.separator();
+
+                let editor = &mut self.shortcut_editor;
+                let keymap = &mut self.keymap;
+                egui::Grid::new("shortcuts_grid")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.name());
+                            let text = if editor.capturing == Some(action) {
+                                "press a key…".to_owned()
+                            } else {
+                                keymap
+                                    .bindings
+                                    .get(&action)
+                                    .map_or("unbound".to_owned(), Binding::name)
+                            };
+                            if ui.button(text).clicked() {
+                                editor.capturing = Some(action);
+                                editor.taking = None;
+                                editor.status = None;
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Clear").clicked() {
+                                    keymap.bindings.remove(&action);
+                                }
+                                let conflicts = keymap.conflicts(action);
+                                if !conflicts.is_empty() {
+                                    let names: Vec<_> =
+                                        conflicts.iter().map(|a| a.name()).collect();
+                                    ui.colored_label(
+                                        ui.visuals().warn_fg_color,
+                                        format!("also bound to {}", names.join(", ")),
+                                    );
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+
+                ui.separator();
+                ui.label("Media keys, pedals and gamepads");
+                ui.weak("Linux only for now, read from /dev/input while the window has focus");
+                for status in self.inputs.status() {
+                    ui.small(status);
+                }
+                let buttons = &mut self.buttons;
+                egui::Grid::new("buttons_grid")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.name());
+                            let text = if editor.capturing_button == Some(action) {
+                                "press a button…".to_owned()
+                            } else {
+                                buttons
+                                    .bindings
+                                    .get(&action)
+                                    .map_or("unbound".to_owned(), |b| b.name())
+                            };
+                            if ui.button(text).clicked() {
+                                editor.capturing_button = Some(action);
+                                editor.status = None;
+                            }
+                            if ui.small_button("Clear").clicked() {
+                                buttons.bindings.remove(&action);
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    if ui.button("Reset to defaults").clicked() {
+                        *keymap = Keymap::default();
+                        *buttons = Default::default();
+                        editor.status = None;
+                    }
+                    if editor.capturing.is_some() {
+                        ui.label("Escape to cancel");
+                    }
+                });
+                if let Some((action, binding, other)) = editor.taking {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("{} is already bound to {}", binding.name(), other.name()),
+                        );
+                        if ui.button(format!("Use it for {}", action.name())).clicked() {
+                            keymap.bind(action, binding);
+                            editor.taking = None;
+                            editor.status = Some(format!("{} is now unbound", other.name()));
+                        }
+                        if ui.button("Cancel").clicked() {
+                            editor.taking = None;
+                        }
+                    });
+                }
+                if let Some(status) = editor.status.as_ref() {
+                    ui.label(status);
+                }
+            });
+        if !open {
+            self.shortcut_editor.capturing = None;
+            self.shortcut_editor.capturing_button = None;
+            self.shortcut_editor.taking = None;
+        }
+        self.shortcuts_open = open;
+    }
+
+    /// Binds the next key pressed to `action`, consuming it so it doesn't also trigger
+    /// whatever it was bound to before.
+    fn capture_binding(&mut self, ctx: &egui::Context, action: Action) {
+        let pressed = ctx.input().events.iter().find_map(|e| match e {
+            egui::Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+            } => Some(Binding::new(*modifiers, *key)),
+            _ => None,
+        });
+        let Some(binding) = pressed else {
+            return;
+        };
+        ctx.input_mut().consume_key(binding.modifiers, binding.key);
+        let editor = &mut self.shortcut_editor;
+        editor.capturing = None;
+        if binding.key == Key::Escape && binding.modifiers.is_none() {
+            return;
+        }
+        // ask before taking a key from another action
+        match self.keymap.bound_to(binding, action) {
+            Some(other) => editor.taking = Some((action, binding, other)),
+            None => {
+                self.keymap.bind(action, binding);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_a_taken_key_moves_it() {
+        let mut keymap = Keymap::default();
+        let m = Binding::new(Modifiers::NONE, Key::M);
+        assert_eq!(keymap.bound_to(m, Action::Rewind), Some(Action::Mark));
+        assert_eq!(keymap.bound_to(m, Action::Mark), None);
+        assert_eq!(keymap.bind(Action::Rewind, m), Some(Action::Mark));
+        assert_eq!(keymap.bindings.get(&Action::Rewind), Some(&m));
+        assert_eq!(keymap.bindings.get(&Action::Mark), None);
+        // rebinding to its own key displaces nothing
+        assert_eq!(keymap.bind(Action::Rewind, m), None);
+        assert!(Action::ALL.iter().all(|a| keymap.conflicts(*a).is_empty()));
+    }
+
+    #[test]
+    fn shared_keys_are_conflicts() {
+        let mut keymap = Keymap::default();
+        let space = keymap.bindings[&Action::PlayPause];
+        keymap.bindings.insert(Action::Mark, space);
+        keymap.bindings.insert(Action::Rewind, space);
+        assert_eq!(
+            keymap.conflicts(Action::PlayPause),
+            vec![Action::Mark, Action::Rewind]
+        );
+        assert_eq!(keymap.conflicts(Action::LoopSegment), vec![]);
+        keymap.bindings.remove(&Action::LoopSegment);
+        assert_eq!(keymap.conflicts(Action::LoopSegment), vec![]);
+    }
+}
@@ -48,6 +48,17 @@ struct SliderSpec {
     largest_finite: f64,
 }
 
+/// A curve drawn in a band above a horizontal slider's rail, lined up with its values,
+/// e.g. a pitch contour.
+pub struct Overlay<'a> {
+    /// Heights from 0 (bottom of the band) to 1 (top). `None` leaves a gap.
+    pub values: &'a [Option<f32>],
+    /// How far along the slider's range each value is from the previous one.
+    pub step: f64,
+    pub height: f32,
+    pub color: Color32,
+}
+
 /// Specifies the orientation of a [`Slider`].
 pub enum SliderOrientation {
     Horizontal,
@@ -93,6 +104,7 @@ pub struct Slider<'a> {
     max_decimals: Option<usize>,
     custom_formatter: Option<NumFormatter<'a>>,
     custom_parser: Option<NumParser<'a>>,
+    overlay: Option<Overlay<'a>>,
 }
 
 impl<'a> Slider<'a> {
@@ -139,9 +151,16 @@ impl<'a> Slider<'a> {
             max_decimals: None,
             custom_formatter: None,
             custom_parser: None,
+            overlay: None,
         }
     }
 
+    /// Draw `overlay` above the rail. Only for horizontal sliders.
+    pub fn overlay(mut self, overlay: Overlay<'a>) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+
     /// Control whether or not the slider shows the current value.
     /// Default: `true`.
     pub fn show_value(mut self, show_value: bool) -> Self {
@@ -532,8 +551,9 @@ impl<'a> Slider<'a> {
 impl<'a> Slider<'a> {
     /// Just the slider, no text
     fn allocate_slider_space(&self, ui: &mut Ui, thickness: f32) -> Response {
+        let overlay = self.overlay.as_ref().map_or(0.0, |o| o.height);
         let desired_size = match self.orientation {
-            SliderOrientation::Horizontal => vec2(ui.spacing().slider_width, thickness),
+            SliderOrientation::Horizontal => vec2(ui.spacing().slider_width, thickness + overlay),
             SliderOrientation::Vertical => vec2(thickness, ui.spacing().slider_width),
         };
         ui.allocate_response(desired_size, Sense::drag())
@@ -541,7 +561,8 @@ impl<'a> Slider<'a> {
 
     /// Just the slider, no text
     fn slider_ui(&mut self, ui: &mut Ui, response: &Response) {
-        let rect = &response.rect;
+        let (band, rect) = self.split_overlay(response.rect);
+        let rect = &rect;
         let position_range = self.position_range(rect);
 
         if let Some(pointer_position_2d) = response.interact_pointer_pos() {
@@ -636,6 +657,10 @@ impl<'a> Slider<'a> {
                 // stroke: ui.visuals().widgets.inactive.bg_stroke,
             });
 
+            if let (Some(band), Some(overlay)) = (band, self.overlay.as_ref()) {
+                self.paint_overlay(ui, overlay, band, position_range.clone());
+            }
+
             let position_1d = self.position_from_value(value, position_range.clone());
             let center = self.marker_center(position_1d, &rail_rect);
 
@@ -675,6 +700,61 @@ impl<'a> Slider<'a> {
         }
     }
 
+    /// The band for the overlay, if there is one, and the rest of `rect` for the rail.
+    fn split_overlay(&self, rect: Rect) -> (Option<Rect>, Rect) {
+        match (&self.overlay, &self.orientation) {
+            (Some(overlay), SliderOrientation::Horizontal) => {
+                let split = rect.top() + overlay.height;
+                (
+                    Some(Rect::from_x_y_ranges(rect.x_range(), rect.top()..=split)),
+                    Rect::from_x_y_ranges(rect.x_range(), split..=rect.bottom()),
+                )
+            }
+            _ => (None, rect),
+        }
+    }
+
+    /// One point per pixel column, with voiced stretches shaded behind the curve.
+    fn paint_overlay(
+        &self,
+        ui: &Ui,
+        overlay: &Overlay<'_>,
+        band: Rect,
+        position_range: RangeInclusive<f32>,
+    ) {
+        let painter = ui.painter().with_clip_rect(band);
+        let shade = overlay.color.linear_multiply(0.08);
+        let stroke = Stroke::new(1.5, overlay.color);
+        let mut line: Vec<Pos2> = vec![];
+        let flush = |line: &mut Vec<Pos2>| {
+            if let (Some(first), Some(last)) = (line.first(), line.last()) {
+                painter.rect_filled(
+                    Rect::from_x_y_ranges(first.x..=last.x, band.y_range()),
+                    0.0,
+                    shade,
+                );
+            }
+            if line.len() > 1 {
+                painter.add(Shape::line(std::mem::take(line), stroke));
+            }
+            line.clear();
+        };
+        let mut x = *position_range.start();
+        while x <= *position_range.end() {
+            let value = self.value_from_position(x, position_range.clone());
+            let ind = (value / overlay.step).floor();
+            match overlay.values.get(ind as usize).copied().flatten() {
+                Some(height) if ind >= 0.0 => {
+                    let y = band.bottom() - height.clamp(0.0, 1.0) * band.height();
+                    line.push(pos2(x, y));
+                }
+                _ => flush(&mut line),
+            }
+            x += 1.0;
+        }
+        flush(&mut line);
+    }
+
     fn marker_center(&self, position_1d: f32, rail_rect: &Rect) -> Pos2 {
         match self.orientation {
             SliderOrientation::Horizontal => pos2(position_1d, rail_rect.center().y),
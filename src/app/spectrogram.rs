@@ -0,0 +1,261 @@
+use egui::{pos2, vec2, Color32, ColorImage, Rect, Sense, Stroke, TextureHandle, TextureOptions};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use super::audio::Pcm;
+use super::TemplateApp;
+use crate::spectrogram::{Stft, HOP, ROWS};
+
+/// Columns per texture. Long files need many textures, no single one could be wide enough.
+const TILE: usize = 512;
+/// Columns sent back from the background thread at a time.
+const CHUNK: usize = 256;
+/// The narrowest view, as a fraction of the file.
+const MIN_VIEW: f32 = 0.001;
+
+enum Message {
+    Len(usize),
+    Columns(Vec<[u8; ROWS]>),
+}
+
+#[derive(Default)]
+struct Tile {
+    texture: Option<TextureHandle>,
+    /// Columns already in the texture.
+    uploaded: usize,
+}
+
+/// The spectrogram of the current file, filled in from the start as the background
+/// thread gets through it.
+pub struct Spectrogram {
+    path: Option<String>,
+    rx: Option<mpsc::Receiver<Message>>,
+    columns: Vec<[u8; ROWS]>,
+    tiles: Vec<Tile>,
+    /// The visible stretch, as fractions of the file.
+    view_start: f32,
+    view_width: f32,
+    palette: [Color32; 256],
+}
+
+impl Default for Spectrogram {
+    fn default() -> Self {
+        Self {
+            path: None,
+            rx: None,
+            columns: vec![],
+            tiles: vec![],
+            view_start: 0.0,
+            view_width: 1.0,
+            palette: palette(),
+        }
+    }
+}
+
+/// Black through purple and orange to pale yellow.
+fn palette() -> [Color32; 256] {
+    const STOPS: [(f32, [f32; 3]); 5] = [
+        (0.0, [0.0, 0.0, 0.0]),
+        (0.3, [60.0, 15.0, 110.0]),
+        (0.55, [180.0, 50.0, 110.0]),
+        (0.8, [250.0, 140.0, 40.0]),
+        (1.0, [252.0, 250.0, 190.0]),
+    ];
+    let mut palette = [Color32::BLACK; 256];
+    for (i, colour) in palette.iter_mut().enumerate() {
+        let t = i as f32 / 255.0;
+        let upper = STOPS
+            .iter()
+            .position(|(at, _)| *at >= t)
+            .unwrap_or(0)
+            .max(1);
+        let ((a, from), (b, to)) = (STOPS[upper - 1], STOPS[upper]);
+        let f = (t - a) / (b - a);
+        let c = |k: usize| (from[k] + (to[k] - from[k]) * f) as u8;
+        *colour = Color32::from_rgb(c(0), c(1), c(2));
+    }
+    palette
+}
+
+impl Spectrogram {
+    fn start(&mut self, path: &str, pcm: Arc<Pcm>) {
+        *self = Self {
+            path: Some(path.to_owned()),
+            palette: self.palette,
+            ..Default::default()
+        };
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut stft = Stft::new(&pcm.mono(), pcm.sample_rate);
+            let len = stft.len();
+            if tx.send(Message::Len(len)).is_err() {
+                return;
+            }
+            for first in (0..len).step_by(CHUNK) {
+                let columns = (first..(first + CHUNK).min(len))
+                    .map(|i| stft.column(i))
+                    .collect();
+                // the receiver goes away when the file changes or the view is closed
+                if tx.send(Message::Columns(columns)).is_err() {
+                    return;
+                }
+            }
+        });
+        self.rx = Some(rx);
+    }
+
+    fn receive(&mut self) {
+        let Some(rx) = self.rx.as_ref() else {
+            return;
+        };
+        loop {
+            match rx.try_recv() {
+                Ok(Message::Len(len)) => {
+                    self.tiles = (0..len.div_ceil(TILE)).map(|_| Tile::default()).collect();
+                }
+                Ok(Message::Columns(columns)) => self.columns.extend(columns),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.rx = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// The texture for tile `ind`, brought up to date with the columns worked out so far.
+    fn tile(&mut self, ctx: &egui::Context, ind: usize) -> Option<&TextureHandle> {
+        let first = ind * TILE;
+        let available = self.columns.len().saturating_sub(first).min(TILE);
+        let tile = self.tiles.get_mut(ind)?;
+        if available > tile.uploaded || tile.texture.is_none() {
+            let mut image = ColorImage::new([TILE, ROWS], Color32::TRANSPARENT);
+            for (x, column) in self.columns[first..first + available].iter().enumerate() {
+                for (row, level) in column.iter().enumerate() {
+                    // highest frequencies at the top
+                    image.pixels[(ROWS - 1 - row) * TILE + x] = self.palette[*level as usize];
+                }
+            }
+            match tile.texture.as_mut() {
+                Some(texture) => texture.set(image, TextureOptions::LINEAR),
+                None => {
+                    tile.texture = Some(ctx.load_texture(
+                        format!("spectrogram_{ind}"),
+                        image,
+                        TextureOptions::LINEAR,
+                    ))
+                }
+            }
+            tile.uploaded = available;
+        }
+        tile.texture.as_ref()
+    }
+
+    /// Zooms by `factor` keeping `anchor` (a fraction of the file) where it is on screen.
+    fn zoom(&mut self, factor: f32, anchor: f32) {
+        let width = (self.view_width * factor).clamp(MIN_VIEW, 1.0);
+        let rel = (anchor - self.view_start) / self.view_width;
+        self.view_start = anchor - rel * width;
+        self.view_width = width;
+        self.clamp_view();
+    }
+
+    fn clamp_view(&mut self) {
+        self.view_start = self.view_start.clamp(0.0, 1.0 - self.view_width);
+    }
+}
+
+impl TemplateApp {
+    pub(super) fn spectrogram_panel(&mut self, ctx: &egui::Context) {
+        if !self.show_spectrogram {
+            // stop working on it, and start over next time if it wasn't finished
+            if self.spectrogram.rx.take().is_some() {
+                self.spectrogram.path = None;
+            }
+            return;
+        }
+        let (Some(path), Some(total)) = (self.picked_path.clone(), self.audio.total_time()) else {
+            return;
+        };
+        let view = &mut self.spectrogram;
+        if view.path.as_deref() != Some(path.as_str()) {
+            if let Some(pcm) = self.audio.pcm() {
+                view.start(&path, pcm);
+            }
+        }
+        view.receive();
+        if view.rx.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+
+        // keep the playhead in sight
+        if self.audio.is_playing()
+            && !(view.view_start..=view.view_start + view.view_width).contains(&self.cur_pos)
+        {
+            view.view_start = self.cur_pos;
+            view.clamp_view();
+        }
+
+        let mut seek = None;
+        egui::TopBottomPanel::bottom("spectrogram")
+            .resizable(true)
+            .default_height(160.0)
+            .show(ctx, |ui| {
+                let size = vec2(ui.available_width(), ui.available_height().max(40.0));
+                let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+                let rect = response.rect;
+                painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+                let view = &mut self.spectrogram;
+                if let Some(pointer) = response.hover_pos() {
+                    let anchor = view.view_start
+                        + (pointer.x - rect.left()) / rect.width() * view.view_width;
+                    let delta = ui.input().scroll_delta;
+                    if delta.y != 0.0 {
+                        view.zoom((-delta.y * 0.005).exp(), anchor);
+                    }
+                    if delta.x != 0.0 {
+                        view.view_start -= delta.x / rect.width() * view.view_width;
+                        view.clamp_view();
+                    }
+                }
+                let (start, width) = (view.view_start, view.view_width);
+                let to_x = |f: f32| rect.left() + (f - start) / width * rect.width();
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    let pos = start + (pointer.x - rect.left()) / rect.width() * width;
+                    seek = Some(pos.clamp(0.0, 1.0));
+                }
+
+                // columns are centred on multiples of the hop
+                let column = HOP.as_secs_f32() / total.as_secs_f32();
+                let first = (start / column).max(0.0) as usize / TILE;
+                let last = ((start + width) / column) as usize / TILE;
+                for ind in first..=last.min(view.tiles.len().saturating_sub(1)) {
+                    let left = (ind * TILE) as f32 * column - column / 2.0;
+                    let right = left + TILE as f32 * column;
+                    let Some(texture) = view.tile(ui.ctx(), ind) else {
+                        continue;
+                    };
+                    painter.image(
+                        texture.id(),
+                        Rect::from_x_y_ranges(to_x(left)..=to_x(right), rect.y_range()),
+                        Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                        Color32::WHITE,
+                    );
+                }
+
+                let mark_stroke = Stroke::new(1.0, Color32::from_white_alpha(90));
+                for mark in self.marks.iter() {
+                    let x = to_x(mark.pos);
+                    painter.vline(x, rect.y_range(), mark_stroke);
+                }
+                let x = to_x(self.cur_pos);
+                painter.vline(x, rect.y_range(), Stroke::new(1.5, Color32::WHITE));
+            });
+
+        if let Some(pos) = seek {
+            self.cur_pos = pos;
+            self.audio.scrub_to(pos);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use egui::plot::{Bar, BarChart, Plot};
+
+use super::TemplateApp;
+use crate::marks;
+use crate::review;
+use crate::stats::{Counters, Playhead};
+
+/// Days shown in the practice chart.
+const CHART_DAYS: i64 = 30;
+
+fn minutes(dur: Duration) -> String {
+    let secs = dur.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+fn counters_row(ui: &mut egui::Ui, name: &str, c: &Counters) {
+    ui.label(name);
+    ui.label(c.plays.to_string());
+    ui.label(c.loops.to_string());
+    ui.label(minutes(c.listening));
+    ui.label(minutes(c.gaps));
+    ui.end_row();
+}
+
+impl TemplateApp {
+    /// Feeds the player's state into the practice stats, once a frame.
+    pub(super) fn track_practice(&mut self, ctx: &egui::Context) {
+        let (Some(path), Some(total)) = (self.picked_path.as_ref(), self.audio.total_time()) else {
+            return;
+        };
+        let segment = total.mul_f32(marks::segment_start(&self.marks, self.cur_pos));
+        let dt = Duration::from_secs_f32(ctx.input().unstable_dt.clamp(0.0, 1.0));
+        let now = Playhead {
+            playing: self.audio.is_playing(),
+            segment,
+            pos: total.mul_f32(self.cur_pos),
+            silence: self.audio.silence(),
+        };
+        self.tracker.tick(&mut self.stats, path, now, dt);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_csv(&mut self, name: &str, csv: String) {
+        if let Some(path) = rfd::FileDialog::new()
+            .set_file_name(name)
+            .add_filter("csv", &["csv"])
+            .save_file()
+        {
+            self.stats_status = Some(match std::fs::write(&path, csv) {
+                Ok(_) => format!("wrote {}", path.display()),
+                Err(e) => format!("couldn't write {}: {e}", path.display()),
+            });
+        }
+    }
+
+    pub(super) fn stats_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.stats_open;
+        egui::Window::new("Statistics")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let stats = &self.stats;
+                let today = review::today();
+                ui.label(format!(
+                    "Streak: {} days (longest {})",
+                    stats.current_streak(today),
+                    stats.longest_streak()
+                ));
+
+                let bars = (today - CHART_DAYS + 1..=today)
+                    .map(|day| {
+                        let listening = stats
+                            .days
+                            .get(&day)
+                            .map_or(0.0, |c| (c.listening + c.gaps).as_secs_f64() / 60.0);
+                        Bar::new((day - today) as f64, listening).name(crate::stats::date(day))
+                    })
+                    .collect();
+                Plot::new("practice_chart")
+                    .height(120.0)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .allow_scroll(false)
+                    .include_y(0.0)
+                    .show(ui, |plot_ui| {
+                        plot_ui.bar_chart(BarChart::new(bars).name("Minutes practised"))
+                    });
+
+                egui::Grid::new("stats_totals")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for heading in ["", "Plays", "Loops", "Listening", "Shadowing"] {
+                            ui.strong(heading);
+                        }
+                        ui.end_row();
+                        let today_counters = stats.days.get(&today).cloned().unwrap_or_default();
+                        counters_row(ui, "Today", &today_counters);
+                        counters_row(ui, "All time", &stats.total());
+                        let file = self.picked_path.as_ref().and_then(|p| stats.files.get(p));
+                        if let Some(file) = file {
+                            counters_row(ui, "This file", &file.total);
+                        }
+                    });
+
+                if let (Some(file), Some(total)) = (
+                    self.picked_path.as_ref().and_then(|p| stats.files.get(p)),
+                    self.audio.total_time(),
+                ) {
+                    ui.collapsing("Segments of this file", |ui| {
+                        egui::Grid::new("stats_segments")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for heading in
+                                    ["Segment", "Plays", "Loops", "Listening", "Shadowing"]
+                                {
+                                    ui.strong(heading);
+                                }
+                                ui.end_row();
+                                for segment in marks::segments(&self.marks, total) {
+                                    let key = segment.start.as_millis() as u64;
+                                    let Some(c) = file.segments.get(&key) else {
+                                        continue;
+                                    };
+                                    let name = if segment.label.is_empty() {
+                                        format!("{:.1}s", segment.start.as_secs_f32())
+                                    } else {
+                                        segment.label.clone()
+                                    };
+                                    counters_row(ui, &name, c);
+                                }
+                            });
+                    });
+                }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.horizontal(|ui| {
+                    if ui.button("Export daily log…").clicked() {
+                        let csv = self.stats.daily_csv();
+                        self.export_csv("practice_log.csv", csv);
+                    }
+                    if ui.button("Export segments…").clicked() {
+                        let csv = self.stats.segments_csv();
+                        self.export_csv("practice_segments.csv", csv);
+                    }
+                });
+                if let Some(status) = self.stats_status.as_ref() {
+                    ui.label(status);
+                }
+            });
+        self.stats_open = open;
+    }
+}
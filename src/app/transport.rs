@@ -0,0 +1,370 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use super::audio::Pcm;
+
+/// Frames between checks for new commands. About 1.5ms at 44.1kHz.
+const POLL_FRAMES: usize = 64;
+
+/// What the UI can ask of a playing [`Transport`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Jump to this frame.
+    Seek(usize),
+    /// Jump back to the start of these frames each time the end is reached.
+    Loop(Option<Range<usize>>),
+    /// Crossfade over this many frames from now on.
+    Fade(usize),
+    /// Play this many frames of silence each time round a loop.
+    Gap(usize),
+    /// Play this many frames (second) of silence on reaching this frame (first), the
+    /// end of the segment playing.
+    GapAt(Option<(usize, usize)>),
+    /// Stop for this long (second, in frames) on reaching each of these frames (first),
+    /// which are sorted.
+    Pauses(Vec<(usize, usize)>),
+}
+
+/// The UI's side of a [`Transport`]. Sending never blocks and the position is an
+/// atomic, so neither side waits on the other.
+pub struct TransportHandle {
+    commands: mpsc::Sender<Command>,
+    position: Arc<AtomicUsize>,
+    loops: Arc<AtomicUsize>,
+    silent: Arc<AtomicUsize>,
+    pub frames: usize,
+    pub sample_rate: u32,
+}
+
+impl TransportHandle {
+    pub fn send(&self, command: Command) {
+        // the transport only goes away with the sink, which goes away with us
+        _ = self.commands.send(command);
+    }
+
+    /// The frame being played.
+    pub fn position(&self) -> usize {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    pub fn time_at(&self, frame: usize) -> Duration {
+        Duration::from_secs_f64(frame as f64 / self.sample_rate as f64)
+    }
+
+    /// How many times playback has gone back round a loop, ever.
+    pub fn loops(&self) -> usize {
+        self.loops.load(Ordering::Relaxed)
+    }
+
+    /// How many frames of gaps and pauses have been played, ever.
+    pub fn silent_frames(&self) -> usize {
+        self.silent.load(Ordering::Relaxed)
+    }
+
+    /// How many frames `dur` lasts, at least one.
+    pub fn frames_in(&self, dur: Duration) -> usize {
+        ((dur.as_secs_f64() * self.sample_rate as f64) as usize).max(1)
+    }
+}
+
+/// Plays a decoded file from wherever it's told to, for as long as the output stream
+/// lasts. Jumps, including going round a loop, crossfade from the old position to the
+/// new one so they don't click. With a gap, a loop fades out into silence and back in
+/// instead. Past the end it plays silence.
+pub struct Transport {
+    pcm: Arc<Pcm>,
+    commands: mpsc::Receiver<Command>,
+    position: Arc<AtomicUsize>,
+    loops: Arc<AtomicUsize>,
+    silent: Arc<AtomicUsize>,
+    /// The next frame to play, and the channel within it.
+    pos: usize,
+    channel: usize,
+    looping: Option<Range<usize>>,
+    /// Where the audio being faded out has got to, while crossfading.
+    fading: Option<usize>,
+    fade_left: usize,
+    fade_len: usize,
+    gap: usize,
+    gap_at: Option<(usize, usize)>,
+    pauses: Vec<(usize, usize)>,
+    /// The pause or gap we've just stopped at, so it isn't taken again straight away.
+    paused_at: Option<usize>,
+    /// Frames of the current gap or pause still to play.
+    silence: usize,
+    until_poll: usize,
+}
+
+impl Transport {
+    pub fn new(pcm: Arc<Pcm>, crossfade: Duration) -> (Self, TransportHandle) {
+        let (tx, rx) = mpsc::channel();
+        let position = Arc::new(AtomicUsize::new(0));
+        let loops = Arc::new(AtomicUsize::new(0));
+        let silent = Arc::new(AtomicUsize::new(0));
+        let handle = TransportHandle {
+            commands: tx,
+            position: position.clone(),
+            loops: loops.clone(),
+            silent: silent.clone(),
+            frames: pcm.frames(),
+            sample_rate: pcm.sample_rate,
+        };
+        let fade_len = handle.frames_in(crossfade);
+        let transport = Self {
+            pcm,
+            commands: rx,
+            position,
+            loops,
+            silent,
+            pos: 0,
+            channel: 0,
+            looping: None,
+            fading: None,
+            fade_left: 0,
+            fade_len,
+            gap: 0,
+            gap_at: None,
+            pauses: vec![],
+            paused_at: None,
+            silence: 0,
+            until_poll: 0,
+        };
+        (transport, handle)
+    }
+
+    fn jump(&mut self, to: usize) {
+        self.fading = Some(self.pos);
+        self.fade_left = self.fade_len;
+        self.pos = to.min(self.pcm.frames());
+    }
+
+    fn poll(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                Command::Seek(to) => {
+                    self.silence = 0;
+                    self.jump(to);
+                }
+                Command::Loop(range) => self.looping = range.filter(|r| !r.is_empty()),
+                Command::Fade(frames) => self.fade_len = frames,
+                Command::Gap(frames) => self.gap = frames,
+                Command::GapAt(gap) => self.gap_at = gap,
+                Command::Pauses(pauses) => self.pauses = pauses,
+            }
+        }
+    }
+
+    /// Starts a pause or gap if we've reached one, fading out into it like a jump.
+    fn check_pause(&mut self) {
+        let found = match self.pauses.binary_search_by_key(&self.pos, |p| p.0) {
+            Ok(ind) => Some(self.pauses[ind].1),
+            Err(_) => self.gap_at.filter(|g| g.0 == self.pos).map(|g| g.1),
+        };
+        match found {
+            Some(len) if self.paused_at != Some(self.pos) => {
+                self.paused_at = Some(self.pos);
+                self.fading = Some(self.pos);
+                self.fade_left = self.fade_len;
+                self.silence = len;
+            }
+            Some(_) => {}
+            None => self.paused_at = None,
+        }
+    }
+
+    fn sample(&self, frame: usize) -> f32 {
+        let ch = self.pcm.channels as usize;
+        self.pcm
+            .samples
+            .get(frame * ch + self.channel)
+            .map_or(0.0, |s| *s as f32)
+    }
+}
+
+impl Iterator for Transport {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.channel == 0 {
+            if self.until_poll == 0 {
+                self.poll();
+                self.until_poll = POLL_FRAMES;
+            }
+            self.until_poll -= 1;
+            if let Some(range) = self.looping.clone() {
+                if self.pos >= range.end && self.silence == 0 {
+                    self.jump(range.start);
+                    self.silence = self.gap;
+                    self.loops.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            if self.silence == 0 {
+                self.check_pause();
+            }
+            self.position.store(self.pos, Ordering::Relaxed);
+        }
+
+        let mut out = if self.silence > 0 {
+            0.0
+        } else {
+            self.sample(self.pos)
+        };
+        if let Some(from) = self.fading {
+            let gain = self.fade_left as f32 / (self.fade_len + 1) as f32;
+            out = out * (1.0 - gain) + self.sample(from) * gain;
+        }
+
+        self.channel += 1;
+        if self.channel == self.pcm.channels as usize {
+            self.channel = 0;
+            if self.silence == 0 {
+                self.pos = (self.pos + 1).min(self.pcm.frames());
+            }
+            if let Some(from) = self.fading.as_mut() {
+                *from += 1;
+                self.fade_left -= 1;
+                if self.fade_left == 0 {
+                    self.fading = None;
+                }
+            }
+            if self.silence > 0 {
+                self.silence -= 1;
+                self.silent.fetch_add(1, Ordering::Relaxed);
+                if self.silence == 0 {
+                    // fade in from the silence, which is what's past the end
+                    self.fading = Some(self.pcm.frames());
+                    self.fade_left = self.fade_len;
+                }
+            }
+        }
+        Some(out as i16)
+    }
+}
+
+impl rodio::Source for Transport {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.pcm.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.pcm.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mono, one sample per frame counting up from 1.
+    fn ramp(frames: i16) -> Arc<Pcm> {
+        Arc::new(Pcm {
+            channels: 1,
+            sample_rate: 1000,
+            samples: (1..=frames).collect(),
+        })
+    }
+
+    #[test]
+    fn plays_through_then_silence() {
+        let (transport, handle) = Transport::new(ramp(3), Duration::from_millis(1));
+        let out: Vec<i16> = transport.take(5).collect();
+        assert_eq!(out, vec![1, 2, 3, 0, 0]);
+        assert_eq!(handle.position(), 3);
+    }
+
+    #[test]
+    fn seeks_crossfade() {
+        let (mut transport, handle) = Transport::new(ramp(200), Duration::from_millis(4));
+        assert_eq!(transport.next(), Some(1));
+        handle.send(Command::Seek(100));
+        // takes effect at the next poll
+        transport.until_poll = 0;
+        let out: Vec<i16> = transport.by_ref().take(5).collect();
+        // from 2, 3, 4, 5 fading into 101, 102, 103, 104
+        assert!(out[0] > 2 && out[0] < 101);
+        assert!(out[1] > out[0] && out[2] > out[1] && out[3] > out[2] && out[3] < 104);
+        assert_eq!(out[4], 105);
+        assert_eq!(handle.position(), 104);
+    }
+
+    #[test]
+    fn fade_length_changes() {
+        let (mut transport, handle) = Transport::new(ramp(200), Duration::from_millis(1));
+        handle.send(Command::Fade(8));
+        handle.send(Command::Seek(100));
+        let out: Vec<i16> = transport.by_ref().take(10).collect();
+        // still fading after the 1ms it would have taken before
+        assert!(out[5] > 1 && out[5] < 106);
+        assert_eq!(out[8], 109);
+    }
+
+    #[test]
+    fn gaps_between_loops() {
+        let (mut transport, handle) = Transport::new(ramp(100), Duration::from_millis(1));
+        handle.send(Command::Loop(Some(10..20)));
+        handle.send(Command::Gap(5));
+        let out: Vec<i16> = transport.by_ref().take(30).collect();
+        assert_eq!(&out[..20], &(1..=20).collect::<Vec<_>>()[..]);
+        // the end of the loop fading out, then nothing
+        assert!(out[20] > 0 && out[20] < 21);
+        assert_eq!(&out[21..25], &[0, 0, 0, 0]);
+        // and back in at the start
+        assert!(out[25] < 11);
+        assert_eq!(out[26], 12);
+        assert_eq!(handle.loops(), 1);
+        assert_eq!(handle.silent_frames(), 5);
+    }
+
+    #[test]
+    fn stops_at_pauses() {
+        let (mut transport, handle) = Transport::new(ramp(100), Duration::from_millis(1));
+        handle.send(Command::Pauses(vec![(5, 3), (8, 0)]));
+        let out: Vec<i16> = transport.by_ref().take(15).collect();
+        assert_eq!(&out[..5], &[1, 2, 3, 4, 5]);
+        // fading out, silence, fading back in to the same place
+        assert!(out[5] > 0 && out[5] < 6);
+        assert_eq!(&out[6..8], &[0, 0]);
+        assert!(out[8] < 6);
+        assert_eq!(&out[9..], &[7, 8, 9, 10, 11, 12]);
+        assert_eq!(handle.position(), 11);
+    }
+
+    #[test]
+    fn gap_at_the_end_of_a_segment() {
+        let (mut transport, handle) = Transport::new(ramp(100), Duration::from_millis(1));
+        handle.send(Command::GapAt(Some((5, 3))));
+        let out: Vec<i16> = transport.by_ref().take(12).collect();
+        assert_eq!(&out[..5], &[1, 2, 3, 4, 5]);
+        assert!(out[5] > 0 && out[5] < 6);
+        assert_eq!(&out[6..8], &[0, 0]);
+        assert!(out[8] < 6);
+        assert_eq!(&out[9..], &[7, 8, 9]);
+        assert_eq!(handle.silent_frames(), 3);
+    }
+
+    #[test]
+    fn loops_go_round() {
+        let (mut transport, handle) = Transport::new(ramp(100), Duration::from_millis(1));
+        handle.send(Command::Loop(Some(10..20)));
+        let out: Vec<i16> = transport.by_ref().take(40).collect();
+        // plays up to the end of the loop, then goes back to its start
+        assert_eq!(&out[..20], &(1..=20).collect::<Vec<_>>()[..]);
+        assert_eq!(out[21], 12);
+        assert_eq!(handle.loops(), 2);
+        handle.send(Command::Loop(None));
+        transport.until_poll = 0;
+        let pos = handle.position();
+        transport.by_ref().take(20).count();
+        assert_eq!(handle.position(), pos + 20);
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{self, SilenceParams};
+use crate::app::audio::{Pcm, SoundData};
+use crate::app::filters::Filters;
+use crate::export::{self, ClipSettings};
+use crate::marks::Segment;
+use crate::render::{self, RenderSettings};
+use crate::srt;
+
+/// Work that runs without opening a window.
+#[derive(Debug)]
+pub enum Job {
+    /// Detect phrases and write them out as SRT cues.
+    AutoMark {
+        input: PathBuf,
+        output: PathBuf,
+        params: SilenceParams,
+    },
+    /// Write every segment to its own audio file.
+    ExportClips {
+        input: PathBuf,
+        marks: Option<PathBuf>,
+        out_dir: PathBuf,
+        settings: ClipSettings,
+    },
+    /// Write a slowed/looped practice track.
+    Render {
+        input: PathBuf,
+        marks: Option<PathBuf>,
+        output: PathBuf,
+        settings: RenderSettings,
+    },
+}
+
+pub fn run(job: Job) -> Result<()> {
+    match job {
+        Job::AutoMark {
+            input,
+            output,
+            params,
+        } => {
+            let pcm = decode(&input)?;
+            let cues: Vec<srt::Cue> =
+                analysis::detect_segments(&pcm.mono(), pcm.sample_rate, &params)
+                    .into_iter()
+                    .map(|(start, end)| srt::Cue {
+                        start,
+                        end,
+                        text: String::new(),
+                    })
+                    .collect();
+            srt::save(&output, &cues)?;
+            println!("wrote {} marks to {}", cues.len(), output.display());
+        }
+        Job::ExportClips {
+            input,
+            marks,
+            out_dir,
+            settings,
+        } => {
+            let pcm = decode(&input)?;
+            let segments = segments(&pcm, marks)?;
+            let paths = export::export_clips(
+                &pcm,
+                &segments,
+                &out_dir,
+                &export::file_stem(&input),
+                &settings,
+                // the clean-up filters are switched on in the window, not here
+                &Filters::default(),
+            )?;
+            println!("wrote {} clips to {}", paths.len(), out_dir.display());
+        }
+        Job::Render {
+            input,
+            marks,
+            output,
+            settings,
+        } => {
+            let pcm = decode(&input)?;
+            let segments = segments(&pcm, marks)?;
+            render::practice_track(&pcm, &segments, &settings, &Filters::default())
+                .write_wav(&output)?;
+            println!(
+                "rendered {} segments to {}",
+                segments.len(),
+                output.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn decode(input: &Path) -> Result<Pcm> {
+    let data =
+        SoundData::new(input).with_context(|| format!("couldn't read {}", input.display()))?;
+    data.decode()
+        .with_context(|| format!("couldn't decode {}", input.display()))
+}
+
+/// Segments from the marks file if there is one, otherwise detected from the audio.
+fn segments(pcm: &Pcm, marks: Option<PathBuf>) -> Result<Vec<Segment>> {
+    Ok(match marks {
+        Some(path) => srt::load(path)?
+            .into_iter()
+            .map(|cue| {
+                let (label, translation) = cue.transcript();
+                Segment {
+                    label,
+                    translation,
+                    ..Segment::new(cue.start, cue.end)
+                }
+            })
+            .collect(),
+        None => analysis::detect_segments(&pcm.mono(), pcm.sample_rate, &Default::default())
+            .into_iter()
+            .map(|(start, end)| Segment::new(start, end))
+            .collect(),
+    })
+}
@@ -0,0 +1,307 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::analysis::SilenceParams;
+use crate::batch::Job;
+use crate::export::{ClipFormat, ClipSettings};
+use crate::project::Project;
+use crate::render::RenderSettings;
+use crate::srt;
+
+pub const USAGE: &str = "\
+usage: mochido [FILE] [--project P] [--speed X] [--start TIME] [--marks marks.srt]
+       mochido auto-mark FILE [-o marks.srt] [--threshold DB] [--min-pause SECS]
+       mochido export-clips FILE [--marks marks.srt] [--pad SECS] [--format wav|flac|ogg] [-o DIR]
+       mochido render FILE [--marks marks.srt] [--speed X] [--repeat N] [--gap SECS]
+                      [--gap-scale X] [--beep] -o OUT.wav
+
+  FILE              audio file to open
+  --project P       open a saved mochido project
+  --speed X         playback speed, between 0.5 and 3.0
+  --start TIME      start position, e.g. 83.4, 1:23.4 or 1:01:23.4
+  --marks FILE      load marks from the cue start times of an SRT file
+  -h, --help        print this help
+
+Without a window:
+  auto-mark         detect phrases and write them as SRT cues
+  export-clips      write each segment as its own WAV
+  render            write a practice track with every segment slowed and repeated
+
+  --threshold DB    loudness, relative to the peak, below which audio is silence (default -35)
+  --min-pause SECS  shortest pause that splits two phrases (default 0.3)
+  --pad SECS        audio kept either side of each clip (default 0.1)
+  --format F        clip format: wav, or flac/ogg when flac, oggenc or ffmpeg is installed
+  --repeat N        times to play each segment (default 2)
+  --gap SECS        silence after each repetition (default 0.5)
+  --gap-scale X     extra silence after each repetition, per second played (default 1)
+  --beep            play a cue tone before each segment
+  -o PATH           where to write the output
+
+Segments come from --marks when given, otherwise they are detected as in auto-mark.";
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+/// What to open when the window starts, parsed from the command line.
+#[derive(Debug, Default)]
+pub struct LaunchOptions {
+    pub file: Option<PathBuf>,
+    pub project: Option<Project>,
+    pub speed: Option<f32>,
+    pub start: Option<Duration>,
+    pub marks: Option<Vec<srt::Cue>>,
+}
+
+pub enum Command {
+    Run(LaunchOptions),
+    Batch(Job),
+    Help,
+}
+
+/// Parses the arguments (without the program name). Files are read here so a bad
+/// path or malformed marks file is reported before any window opens.
+pub fn parse<I, S>(args: I) -> Result<Command>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut opts = LaunchOptions::default();
+    let mut args = args.into_iter().map(Into::into).peekable();
+
+    if let Some(sub) =
+        args.next_if(|a| ["auto-mark", "export-clips", "render"].contains(&a.as_str()))
+    {
+        return parse_batch(&sub, args);
+    }
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| anyhow!("`{name}` expects a value"))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--project" => {
+                let path = value("--project")?;
+                let project = Project::load(&path)?;
+                if let Some(audio) = project.audio_path.as_deref() {
+                    audio_file(audio).with_context(|| format!("in project {path}"))?;
+                }
+                opts.project = Some(project);
+            }
+            "--speed" => opts.speed = Some(parse_speed(&value("--speed")?)?),
+            "--start" => {
+                let v = value("--start")?;
+                opts.start = Some(parse_position(&v)?);
+            }
+            "--marks" => {
+                let path = value("--marks")?;
+                opts.marks = Some(srt::load(path)?);
+            }
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                bail!("unknown option `{flag}`")
+            }
+            file => {
+                if opts.file.is_some() {
+                    bail!("only one audio file can be opened, got an extra `{file}`");
+                }
+                opts.file = Some(audio_file(file)?);
+            }
+        }
+    }
+
+    Ok(Command::Run(opts))
+}
+
+fn parse_batch(sub: &str, mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut input = None;
+    let mut output = None;
+    let mut marks = None;
+    let mut params = SilenceParams::default();
+    let mut settings = RenderSettings::default();
+    let mut clip_settings = ClipSettings::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| anyhow!("`{name}` expects a value"))
+        };
+        match (sub, arg.as_str()) {
+            (_, "-h" | "--help") => return Ok(Command::Help),
+            (_, "-o" | "--output") => output = Some(PathBuf::from(value("-o")?)),
+            ("export-clips" | "render", "--marks") => {
+                let path = existing_file(&value("--marks")?)?;
+                // fail early on a bad marks file rather than after decoding
+                srt::load(&path)?;
+                marks = Some(path);
+            }
+            ("export-clips", "--pad") => clip_settings.padding = parse_secs(&value("--pad")?)?,
+            ("export-clips", "--format") => {
+                let v = value("--format")?;
+                let format = ClipFormat::from_extension(&v)
+                    .ok_or_else(|| anyhow!("unknown format `{v}`, expected wav, flac or ogg"))?;
+                if !format.available() {
+                    bail!("no {v} encoder found, install one or use `--format wav`");
+                }
+                clip_settings.format = format;
+            }
+            ("auto-mark", "--threshold") => {
+                let v = value("--threshold")?;
+                params.threshold_db = v
+                    .parse()
+                    .ok()
+                    .filter(|db: &f32| db.is_finite() && *db <= 0.0)
+                    .ok_or_else(|| anyhow!("threshold must be 0 dB or below, got `{v}`"))?;
+            }
+            ("auto-mark", "--min-pause") => {
+                params.min_silence = parse_secs(&value("--min-pause")?)?
+            }
+            ("render", "--speed") => settings.speed = parse_speed(&value("--speed")?)?,
+            ("render", "--repeat") => {
+                let v = value("--repeat")?;
+                settings.repeat =
+                    v.parse().ok().filter(|n| *n > 0).ok_or_else(|| {
+                        anyhow!("repeat must be a positive whole number, got `{v}`")
+                    })?;
+            }
+            ("render", "--gap") => settings.gap = parse_secs(&value("--gap")?)?,
+            ("render", "--gap-scale") => {
+                let v = value("--gap-scale")?;
+                settings.gap_scale = v
+                    .parse()
+                    .ok()
+                    .filter(|x: &f32| x.is_finite() && *x >= 0.0)
+                    .ok_or_else(|| anyhow!("gap scale must be zero or more, got `{v}`"))?;
+            }
+            ("render", "--beep") => settings.beep = true,
+            (_, flag) if flag.starts_with('-') && flag.len() > 1 => {
+                bail!("unknown option `{flag}` for `{sub}`")
+            }
+            (_, file) => {
+                if input.is_some() {
+                    bail!("`{sub}` takes one audio file, got an extra `{file}`");
+                }
+                input = Some(audio_file(file)?);
+            }
+        }
+    }
+
+    let input = input.ok_or_else(|| anyhow!("`{sub}` needs an audio file"))?;
+    let stem = input.with_extension("");
+    let job = match sub {
+        "auto-mark" => Job::AutoMark {
+            output: output.unwrap_or_else(|| input.with_extension("srt")),
+            input,
+            params,
+        },
+        "export-clips" => Job::ExportClips {
+            out_dir: output.unwrap_or_else(|| PathBuf::from(format!("{}_clips", stem.display()))),
+            input,
+            marks,
+            settings: clip_settings,
+        },
+        _ => Job::Render {
+            output: output
+                .ok_or_else(|| anyhow!("`render` needs an output file, pass `-o OUT.wav`"))?,
+            input,
+            marks,
+            settings,
+        },
+    };
+    Ok(Command::Batch(job))
+}
+
+fn existing_file(path: &str) -> Result<PathBuf> {
+    let p = PathBuf::from(path);
+    if !p.is_file() {
+        bail!("file `{path}` doesn't exist");
+    }
+    Ok(p)
+}
+
+/// An existing file that also looks like audio we can play, going by its header. The
+/// window decodes it in the background, so this is the last chance to say it won't.
+fn audio_file(path: &str) -> Result<PathBuf> {
+    let p = existing_file(path)?;
+    let file = std::fs::File::open(&p).with_context(|| format!("couldn't open `{path}`"))?;
+    rodio::Decoder::new(std::io::BufReader::new(file))
+        .with_context(|| format!("`{path}` isn't an audio file that can be played"))?;
+    Ok(p)
+}
+
+fn parse_speed(v: &str) -> Result<f32> {
+    let speed: f32 = v.parse().with_context(|| format!("invalid speed `{v}`"))?;
+    if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        bail!("speed must be between {MIN_SPEED} and {MAX_SPEED}, got {speed}");
+    }
+    Ok(speed)
+}
+
+fn parse_secs(v: &str) -> Result<Duration> {
+    v.parse::<f64>()
+        .ok()
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| anyhow!("expected a number of seconds, got `{v}`"))
+}
+
+/// Parses a position given as `SS.s`, `MM:SS.s` or `HH:MM:SS.s`.
+pub fn parse_position(s: &str) -> Result<Duration> {
+    let invalid = || format!("invalid position `{s}`, expected e.g. 83.4, 1:23.4 or 1:01:23.4");
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() > 3 {
+        bail!(invalid());
+    }
+    let (secs, whole) = parts.split_last().ok_or_else(|| anyhow!(invalid()))?;
+    let mut total: f64 = secs.parse().with_context(invalid)?;
+    if !total.is_finite() || total < 0.0 || (!whole.is_empty() && total >= 60.0) {
+        bail!(invalid());
+    }
+    for (unit, part) in whole.iter().rev().enumerate() {
+        let v: u64 = part.parse().with_context(invalid)?;
+        if unit == 0 && whole.len() > 1 && v >= 60 {
+            bail!(invalid());
+        }
+        total += v as f64 * 60f64.powi(unit as i32 + 1);
+    }
+    Ok(Duration::from_secs_f64(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_in_any_form() {
+        let secs = |s: &str| parse_position(s).unwrap().as_secs_f64();
+        assert_eq!(secs("83.4"), 83.4);
+        assert_eq!(secs("1:23.4"), 83.4);
+        assert_eq!(secs("1:01:23.5"), 3683.5);
+        assert_eq!(secs("90"), 90.0);
+        for bad in [
+            "", "1:60", "1:60:00", "1:2:3:4", "-1", "a:10", "NaN", "1:inf",
+        ] {
+            assert!(parse_position(bad).is_err(), "{bad} was accepted");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(["no/such/file.mp3"]).is_err());
+        assert!(parse(["--speed", "5"]).is_err());
+        assert!(parse(["--start"]).is_err());
+        assert!(parse(["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn threshold_is_below_the_peak() {
+        for bad in ["6", "NaN", "-inf", "loud"] {
+            let err = parse_batch(
+                "auto-mark",
+                ["--threshold".to_owned(), bad.to_owned()].into_iter(),
+            );
+            assert!(err.is_err(), "{bad} was accepted");
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use std::time::Duration;
+
+use crate::marks::{Mark, Span};
+
+/// A change to the marks or captured spans that knows how to undo itself. The
+/// operations below work out which edit does what the user asked for; [`History`]
+/// applies them.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Edit {
+    Insert {
+        ind: usize,
+        mark: Mark,
+    },
+    Remove {
+        ind: usize,
+        mark: Mark,
+    },
+    Update {
+        ind: usize,
+        before: Mark,
+        after: Mark,
+    },
+    /// Everything at once, for changes that touch most marks anyway.
+    Replace {
+        before: Vec<Mark>,
+        after: Vec<Mark>,
+    },
+    InsertSpan {
+        ind: usize,
+        span: Span,
+    },
+    RemoveSpan {
+        ind: usize,
+        span: Span,
+    },
+    UpdateSpan {
+        ind: usize,
+        before: Span,
+        after: Span,
+    },
+    /// Several edits done and undone together, applied in order.
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    pub fn apply(&self, marks: &mut Vec<Mark>, spans: &mut Vec<Span>) {
+        match self {
+            Edit::Insert { ind, mark } => marks.insert(*ind, mark.clone()),
+            Edit::Remove { ind, .. } => {
+                marks.remove(*ind);
+            }
+            Edit::Update { ind, after, .. } => marks[*ind] = after.clone(),
+            Edit::Replace { after, .. } => *marks = after.clone(),
+            Edit::InsertSpan { ind, span } => spans.insert(*ind, span.clone()),
+            Edit::RemoveSpan { ind, .. } => {
+                spans.remove(*ind);
+            }
+            Edit::UpdateSpan { ind, after, .. } => spans[*ind] = after.clone(),
+            Edit::Batch(edits) => edits.iter().for_each(|e| e.apply(marks, spans)),
+        }
+    }
+
+    pub fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::Insert { ind, mark } => Edit::Remove { ind, mark },
+            Edit::Remove { ind, mark } => Edit::Insert { ind, mark },
+            Edit::Update { ind, before, after } => Edit::Update {
+                ind,
+                before: after,
+                after: before,
+            },
+            Edit::Replace { before, after } => Edit::Replace {
+                before: after,
+                after: before,
+            },
+            Edit::InsertSpan { ind, span } => Edit::RemoveSpan { ind, span },
+            Edit::RemoveSpan { ind, span } => Edit::InsertSpan { ind, span },
+            Edit::UpdateSpan { ind, before, after } => Edit::UpdateSpan {
+                ind,
+                before: after,
+                after: before,
+            },
+            Edit::Batch(edits) => Edit::Batch(edits.iter().rev().map(Edit::inverse).collect()),
+        }
+    }
+}
+
+/// Index of the mark starting the segment under `pos`, if it isn't the stretch before
+/// the first mark.
+fn start_ind(marks: &[Mark], pos: f32) -> Option<usize> {
+    marks.partition_point(|m| m.pos <= pos).checked_sub(1)
+}
+
+/// Index of the mark ending the segment under `pos`, if it isn't the last segment.
+fn end_ind(marks: &[Mark], pos: f32) -> Option<usize> {
+    let ind = marks.partition_point(|m| m.pos <= pos);
+    (ind < marks.len()).then_some(ind)
+}
+
+fn joined(a: &str, b: &str) -> String {
+    match (a.is_empty(), b.is_empty()) {
+        (_, true) => a.to_owned(),
+        (true, false) => b.to_owned(),
+        (false, false) => format!("{a} {b}"),
+    }
+}
+
+/// Adds a mark at `pos`, unless there's one there already.
+pub fn split(marks: &[Mark], pos: f32) -> Option<Edit> {
+    if marks.iter().any(|m| m.pos == pos) {
+        return None;
+    }
+    let ind = marks.partition_point(|m| m.pos < pos);
+    Some(Edit::Insert {
+        ind,
+        mark: Mark::new(pos),
+    })
+}
+
+/// Deletes mark `ind`, text and all.
+pub fn remove(marks: &[Mark], ind: usize) -> Option<Edit> {
+    let mark = marks.get(ind)?.clone();
+    Some(Edit::Remove { ind, mark })
+}
+
+/// Swaps all the marks for `after`, e.g. ones read from subtitles.
+pub fn replace(marks: &[Mark], after: Vec<Mark>) -> Option<Edit> {
+    (marks != after).then(|| Edit::Replace {
+        before: marks.to_vec(),
+        after,
+    })
+}
+
+/// Records mark `ind` having been changed in place from `before`, e.g. by typing
+/// into its label. `None` if it's the same as it was.
+pub fn update(marks: &[Mark], ind: usize, before: Mark) -> Option<Edit> {
+    let after = marks.get(ind).filter(|m| **m != before)?.clone();
+    Some(Edit::Update { ind, before, after })
+}
+
+/// Adds a captured span after the others.
+pub fn add_span(spans: &[Span], span: Span) -> Edit {
+    Edit::InsertSpan {
+        ind: spans.len(),
+        span,
+    }
+}
+
+pub fn remove_span(spans: &[Span], ind: usize) -> Option<Edit> {
+    let span = spans.get(ind)?.clone();
+    Some(Edit::RemoveSpan { ind, span })
+}
+
+/// Records span `ind` having been changed in place from `before`, like [`update`].
+pub fn update_span(spans: &[Span], ind: usize, before: Span) -> Option<Edit> {
+    let after = spans.get(ind).filter(|s| **s != before)?.clone();
+    Some(Edit::UpdateSpan { ind, before, after })
+}
+
+/// Joins the segment under `pos` with the one after it. The text of both is kept, so
+/// there's no merging into the stretch before the first mark while that mark has any.
+pub fn merge_next(marks: &[Mark], pos: f32) -> Option<Edit> {
+    let end = end_ind(marks, pos)?;
+    merge_at(marks, end)
+}
+
+/// Joins the segment under `pos` with the one before it.
+pub fn merge_prev(marks: &[Mark], pos: f32) -> Option<Edit> {
+    let start = start_ind(marks, pos)?;
+    merge_at(marks, start)
+}
+
+/// Removes mark `ind`, moving its text onto the segment before. The stretch before the
+/// first mark has nowhere to keep text or overrides, so mark 0 only goes if it has none.
+fn merge_at(marks: &[Mark], ind: usize) -> Option<Edit> {
+    let removed = marks.get(ind)?.clone();
+    let remove = Edit::Remove {
+        ind,
+        mark: removed.clone(),
+    };
+    let Some(prev) = ind.checked_sub(1) else {
+        return (removed == Mark::new(removed.pos)).then_some(remove);
+    };
+    let before = marks[prev].clone();
+    let after = Mark {
+        label: joined(&before.label, &removed.label),
+        translation: joined(&before.translation, &removed.translation),
+        ..before.clone()
+    };
+    Some(Edit::Batch(vec![
+        Edit::Update {
+            ind: prev,
+            before,
+            after,
+        },
+        remove,
+    ]))
+}
+
+/// Moves the start of the segment under `pos` up to `pos`. The segment before gets
+/// longer; marks are boundaries, there's no gap between segments.
+pub fn trim_start(marks: &[Mark], pos: f32) -> Option<Edit> {
+    let ind = start_ind(marks, pos)?;
+    move_mark(marks, ind, pos)
+}
+
+/// Moves the end of the segment under `pos` back to `pos`.
+pub fn trim_end(marks: &[Mark], pos: f32) -> Option<Edit> {
+    let ind = end_ind(marks, pos)?;
+    move_mark(marks, ind, pos)
+}
+
+fn move_mark(marks: &[Mark], ind: usize, pos: f32) -> Option<Edit> {
+    let before = marks[ind].clone();
+    (before.pos != pos).then(|| Edit::Update {
+        ind,
+        after: Mark {
+            pos,
+            ..before.clone()
+        },
+        before,
+    })
+}
+
+/// Moves every mark later by `secs` (earlier if negative) in a file `total` long, for
+/// when the marks were made against a version with a different intro. Marks pushed off
+/// either end are dropped.
+pub fn shift(marks: &[Mark], secs: f32, total: Duration) -> Option<Edit> {
+    let by = secs / total.as_secs_f32();
+    if by == 0.0 || !by.is_finite() || marks.is_empty() {
+        return None;
+    }
+    let after = marks
+        .iter()
+        .map(|m| Mark {
+            pos: m.pos + by,
+            ..m.clone()
+        })
+        .filter(|m| (0.0..=1.0).contains(&m.pos))
+        .collect();
+    Some(Edit::Replace {
+        before: marks.to_vec(),
+        after,
+    })
+}
+
+/// How many edits [`History`] keeps. It's saved with the project, so this keeps
+/// a long session's worth without letting the file grow forever.
+const LIMIT: usize = 500;
+
+/// Edits done so far, and those undone since, newest last.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct History {
+    pub done: Vec<Edit>,
+    pub undone: Vec<Edit>,
+}
+
+impl History {
+    pub fn apply(&mut self, edit: Edit, marks: &mut Vec<Mark>, spans: &mut Vec<Span>) {
+        edit.apply(marks, spans);
+        self.done.push(edit);
+        self.undone.clear();
+        if self.done.len() > LIMIT {
+            self.done.drain(..self.done.len() - LIMIT);
+        }
+    }
+
+    /// Returns `false` if there was nothing to undo.
+    pub fn undo(&mut self, marks: &mut Vec<Mark>, spans: &mut Vec<Span>) -> bool {
+        let Some(edit) = self.done.pop() else {
+            return false;
+        };
+        edit.inverse().apply(marks, spans);
+        self.undone.push(edit);
+        true
+    }
+
+    /// Returns `false` if there was nothing to redo.
+    pub fn redo(&mut self, marks: &mut Vec<Mark>, spans: &mut Vec<Span>) -> bool {
+        let Some(edit) = self.undone.pop() else {
+            return false;
+        };
+        edit.apply(marks, spans);
+        self.done.push(edit);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labelled(marks: &[(f32, &str)]) -> Vec<Mark> {
+        marks
+            .iter()
+            .map(|(pos, label)| Mark {
+                label: label.to_string(),
+                ..Mark::new(*pos)
+            })
+            .collect()
+    }
+
+    fn positions(marks: &[Mark]) -> Vec<f32> {
+        marks.iter().map(|m| m.pos).collect()
+    }
+
+    /// Applies `edit`, then checks undoing it gets back where we started.
+    fn round_trip(marks: &[Mark], edit: Option<Edit>) -> Vec<Mark> {
+        let mut history = History::default();
+        let mut edited = marks.to_vec();
+        let spans = &mut vec![];
+        history.apply(edit.expect("no edit"), &mut edited, spans);
+        let after = edited.clone();
+        assert!(history.undo(&mut edited, spans));
+        assert_eq!(edited, marks);
+        assert!(history.redo(&mut edited, spans));
+        assert_eq!(edited, after);
+        after
+    }
+
+    #[test]
+    fn split_adds_a_mark_in_order() {
+        let marks = labelled(&[(0.2, "a"), (0.6, "b")]);
+        let after = round_trip(&marks, split(&marks, 0.4));
+        assert_eq!(positions(&after), vec![0.2, 0.4, 0.6]);
+        assert_eq!(split(&marks, 0.2), None);
+    }
+
+    #[test]
+    fn merge_keeps_both_texts() {
+        let marks = labelled(&[(0.2, "a"), (0.4, "b"), (0.6, "c")]);
+        let after = round_trip(&marks, merge_next(&marks, 0.3));
+        assert_eq!(positions(&after), vec![0.2, 0.6]);
+        assert_eq!(after[0].label, "a b");
+
+        let after = round_trip(&marks, merge_prev(&marks, 0.7));
+        assert_eq!(positions(&after), vec![0.2, 0.4]);
+        assert_eq!(after[1].label, "b c");
+
+        // the first segment has no mark of its own to take "a"
+        assert_eq!(merge_next(&marks, 0.1), None);
+        let mut blank = marks.clone();
+        blank[0].label.clear();
+        let after = round_trip(&blank, merge_next(&blank, 0.1));
+        assert_eq!(positions(&after), vec![0.4, 0.6]);
+        assert_eq!(merge_prev(&blank, 0.3), merge_next(&blank, 0.1));
+        // nothing after the last segment, nothing before the first
+        assert_eq!(merge_next(&marks, 0.7), None);
+        assert_eq!(merge_prev(&marks, 0.1), None);
+    }
+
+    #[test]
+    fn trim_moves_the_boundaries() {
+        let marks = labelled(&[(0.2, "a"), (0.6, "b")]);
+        let after = round_trip(&marks, trim_start(&marks, 0.3));
+        assert_eq!(positions(&after), vec![0.3, 0.6]);
+        assert_eq!(after[0].label, "a");
+        let after = round_trip(&marks, trim_end(&marks, 0.5));
+        assert_eq!(positions(&after), vec![0.2, 0.5]);
+        assert_eq!(trim_start(&marks, 0.1), None);
+        assert_eq!(trim_end(&marks, 0.7), None);
+        assert_eq!(trim_start(&marks, 0.2), None);
+    }
+
+    #[test]
+    fn shift_moves_everything_and_drops_overflow() {
+        let total = Duration::from_secs(100);
+        let marks = labelled(&[(0.02, "a"), (0.5, "b"), (0.97, "c")]);
+        let after = round_trip(&marks, shift(&marks, 5.0, total));
+        assert_eq!(after.len(), 2);
+        assert!((after[0].pos - 0.07).abs() < 1e-6);
+        assert_eq!(after[1].label, "b");
+        let after = round_trip(&marks, shift(&marks, -5.0, total));
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[0].label, "b");
+        assert_eq!(shift(&marks, 0.0, total), None);
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let (mut marks, spans) = (vec![], &mut vec![]);
+        let mut history = History::default();
+        history.apply(split(&marks, 0.5).unwrap(), &mut marks, spans);
+        history.apply(split(&marks, 0.7).unwrap(), &mut marks, spans);
+        assert!(history.undo(&mut marks, spans));
+        history.apply(split(&marks, 0.1).unwrap(), &mut marks, spans);
+        assert!(!history.redo(&mut marks, spans));
+        assert_eq!(positions(&marks), vec![0.1, 0.5]);
+        assert!(history.undo(&mut marks, spans));
+        assert!(history.undo(&mut marks, spans));
+        assert!(!history.undo(&mut marks, spans));
+        assert!(marks.is_empty());
+    }
+
+    #[test]
+    fn remove_and_replace_undo() {
+        let marks = labelled(&[(0.2, "a"), (0.6, "b")]);
+        let after = round_trip(&marks, remove(&marks, 0));
+        assert_eq!(after, labelled(&[(0.6, "b")]));
+        assert_eq!(remove(&marks, 2), None);
+        let imported = labelled(&[(0.1, "x")]);
+        let after = round_trip(&marks, replace(&marks, imported.clone()));
+        assert_eq!(after, imported);
+        assert_eq!(replace(&marks, marks.clone()), None);
+    }
+
+    #[test]
+    fn history_forgets_the_oldest() {
+        let (mut marks, spans) = (vec![], &mut vec![]);
+        let mut history = History::default();
+        for i in 0..LIMIT + 10 {
+            history.apply(split(&marks, i as f32 / 1000.0).unwrap(), &mut marks, spans);
+        }
+        while history.undo(&mut marks, spans) {}
+        assert_eq!(marks.len(), 10);
+    }
+
+    #[test]
+    fn typed_text_undoes_on_its_own() {
+        let (mut marks, spans) = (labelled(&[(0.2, ""), (0.6, "")]), &mut vec![]);
+        let mut history = History::default();
+        let before = marks[0].clone();
+        marks[0].label = "hola".into();
+        history.apply(update(&marks, 0, before).unwrap(), &mut marks, spans);
+        history.apply(
+            shift(&marks, 1.0, Duration::from_secs(10)).unwrap(),
+            &mut marks,
+            spans,
+        );
+        assert!(history.undo(&mut marks, spans));
+        assert_eq!(marks, labelled(&[(0.2, "hola"), (0.6, "")]));
+        assert!(history.undo(&mut marks, spans));
+        assert_eq!(marks, labelled(&[(0.2, ""), (0.6, "")]));
+        assert_eq!(update(&marks, 0, marks[0].clone()), None);
+    }
+
+    #[test]
+    fn spans_undo_too() {
+        let (mut marks, mut spans) = (vec![], vec![]);
+        let mut history = History::default();
+        history.apply(
+            add_span(&spans, Span::new(0.1, 0.2)),
+            &mut marks,
+            &mut spans,
+        );
+        history.apply(
+            add_span(&spans, Span::new(0.3, 0.4)),
+            &mut marks,
+            &mut spans,
+        );
+        spans[1].label = "dos".into();
+        let edit = update_span(&spans, 1, Span::new(0.3, 0.4)).unwrap();
+        history.apply(edit, &mut marks, &mut spans);
+        let labelled = spans[1].clone();
+        history.apply(remove_span(&spans, 0).unwrap(), &mut marks, &mut spans);
+        assert_eq!(spans, vec![labelled.clone()]);
+        assert!(history.undo(&mut marks, &mut spans));
+        assert_eq!(spans, vec![Span::new(0.1, 0.2), labelled]);
+        while history.undo(&mut marks, &mut spans) {}
+        assert!(spans.is_empty());
+        assert_eq!(remove_span(&spans, 0), None);
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::app::audio::Pcm;
+use crate::app::filters::Filters;
+use crate::marks::Segment;
+
+/// Fade at both ends of a clip so the cut doesn't click.
+const CLIP_FADE: Duration = Duration::from_millis(10);
+
+/// Builds an encoder's arguments from the input and output paths.
+type EncoderArgs = fn(&Path, &Path) -> Vec<String>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum ClipFormat {
+    Wav,
+    Flac,
+    Ogg,
+}
+
+impl ClipFormat {
+    pub const ALL: [ClipFormat; 3] = [ClipFormat::Wav, ClipFormat::Flac, ClipFormat::Ogg];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ClipFormat::Wav => "wav",
+            ClipFormat::Flac => "flac",
+            ClipFormat::Ogg => "ogg",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.extension().eq_ignore_ascii_case(ext))
+    }
+
+    /// WAV is written directly, the others need an encoder installed on the `PATH`.
+    /// Looked up once, the export window asks every frame.
+    pub fn available(&self) -> bool {
+        static AVAILABLE: OnceLock<[bool; 3]> = OnceLock::new();
+        let available = AVAILABLE
+            .get_or_init(|| ClipFormat::ALL.map(|f| f == ClipFormat::Wav || f.encoder().is_some()));
+        available[*self as usize]
+    }
+
+    /// The first installed encoder for this format, with its arguments for
+    /// turning `input` into `output`.
+    fn encoder(&self) -> Option<(PathBuf, EncoderArgs)> {
+        let candidates: &[(&str, EncoderArgs)] = match self {
+            ClipFormat::Wav => &[],
+            ClipFormat::Flac => &[
+                ("flac", |i, o| args(&["-s", "-f", "-o"], o, &[], i)),
+                ("ffmpeg", |i, o| {
+                    args(&["-loglevel", "error", "-y", "-i"], i, &[], o)
+                }),
+            ],
+            ClipFormat::Ogg => &[
+                ("oggenc", |i, o| args(&["-Q", "-o"], o, &[], i)),
+                ("ffmpeg", |i, o| {
+                    args(
+                        &["-loglevel", "error", "-y", "-i"],
+                        i,
+                        &["-c:a", "libvorbis"],
+                        o,
+                    )
+                }),
+            ],
+        };
+        candidates
+            .iter()
+            .find_map(|(name, args)| find_program(name).map(|p| (p, *args)))
+    }
+}
+
+fn args(before: &[&str], first: &Path, between: &[&str], second: &Path) -> Vec<String> {
+    before
+        .iter()
+        .map(|s| s.to_string())
+        .chain([first.display().to_string()])
+        .chain(between.iter().map(|s| s.to_string()))
+        .chain([second.display().to_string()])
+        .collect()
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    let exe = format!("{name}{}", std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&exe))
+        .find(|p| p.is_file())
+}
+
+/// How segments are written out as clips.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ClipSettings {
+    /// Extra audio kept on either side of the segment.
+    pub padding: Duration,
+    pub format: ClipFormat,
+}
+
+impl Default for ClipSettings {
+    fn default() -> Self {
+        Self {
+            padding: Duration::from_millis(100),
+            format: ClipFormat::Wav,
+        }
+    }
+}
+
+/// The segment plus padding through `filters`, faded at both ends.
+pub fn clip(pcm: &Pcm, segment: &Segment, padding: Duration, filters: &Filters) -> Pcm {
+    let clip = pcm.slice(segment.start.saturating_sub(padding), segment.end + padding);
+    let mut clip = filters.process(&clip);
+    clip.fade_edges(CLIP_FADE);
+    clip
+}
+
+pub fn export_clip(
+    pcm: &Pcm,
+    segment: &Segment,
+    path: &Path,
+    settings: &ClipSettings,
+    filters: &Filters,
+) -> Result<()> {
+    let clip = clip(pcm, segment, settings.padding, filters);
+    if settings.format == ClipFormat::Wav {
+        return clip.write_wav(path);
+    }
+    let Some((program, args)) = settings.format.encoder() else {
+        bail!(
+            "no {} encoder found, install one or export as WAV",
+            settings.format.extension()
+        );
+    };
+    let wav = path.with_extension("tmp.wav");
+    clip.write_wav(&wav)?;
+    let status = process::Command::new(&program)
+        .args(args(&wav, path))
+        .stdin(process::Stdio::null())
+        .status()
+        .with_context(|| format!("couldn't run {}", program.display()));
+    _ = std::fs::remove_file(&wav);
+    if !status?.success() {
+        bail!("{} failed to write {}", program.display(), path.display());
+    }
+    Ok(())
+}
+
+/// Writes every segment into `dir`, named from its label. Returns the written paths.
+pub fn export_clips(
+    pcm: &Pcm,
+    segments: &[Segment],
+    dir: &Path,
+    stem: &str,
+    settings: &ClipSettings,
+    filters: &Filters,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+    segments
+        .iter()
+        .enumerate()
+        .map(|(ind, segment)| {
+            let path = dir.join(file_name(ind, segment, stem, settings.format));
+            export_clip(pcm, segment, &path, settings, filters)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// `003_label.wav`, or `stem_003.wav` for unlabelled segments. The number keeps the
+/// clips in order and the names unique: `ind` is the segment's place in
+/// [`crate::marks::segments`], so a clip is named the same however it's exported.
+pub fn file_name(ind: usize, segment: &Segment, stem: &str, format: ClipFormat) -> String {
+    let label: String = segment
+        .label
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_control() || r#"<>:"/\|?*"#.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(60)
+        .collect();
+    let label = label.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if label.is_empty() {
+        format!("{stem}_{:03}.{}", ind + 1, format.extension())
+    } else {
+        format!("{:03}_{label}.{}", ind + 1, format.extension())
+    }
+}
+
+pub fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "segment".to_owned())
+}
@@ -1,4 +1,19 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+mod align;
+mod analysis;
+mod anki;
 mod app;
+pub mod batch;
+pub mod cli;
+mod edit;
+mod export;
+mod marks;
+mod navigation;
+mod project;
+mod render;
+mod review;
+mod spectrogram;
+mod srt;
+mod stats;
 pub use app::TemplateApp;
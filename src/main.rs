@@ -1,6 +1,8 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+#[cfg(not(target_arch = "wasm32"))]
+use mochido::cli;
 use mochido::TemplateApp;
 
 // When compiling natively:
@@ -9,11 +11,30 @@ fn main() {
     // Log to stdout (if you run with `RUST_LOG=debug`).
     tracing_subscriber::fmt::init();
 
+    let opts = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Run(opts)) => opts,
+        Ok(cli::Command::Batch(job)) => {
+            if let Err(e) = mochido::batch::run(job) {
+                eprintln!("error: {e:#}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {e:#}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
-        "eframe template",
+        "Mochido",
         native_options,
-        Box::new(|cc| Box::new(TemplateApp::new(cc))),
+        Box::new(|cc| Box::new(TemplateApp::with_options(cc, opts))),
     );
 }
 
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+/// A point on the timeline, stored as a fraction of the file like the slider value.
+/// The label (usually the transcript), translation and overrides describe the segment
+/// that starts here.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(from = "MarkRepr")]
+pub struct Mark {
+    pub pos: f32,
+    pub label: String,
+    pub translation: String,
+    pub overrides: Overrides,
+}
+
+impl Mark {
+    pub fn new(pos: f32) -> Self {
+        Self {
+            pos,
+            label: String::new(),
+            translation: String::new(),
+            overrides: Default::default(),
+        }
+    }
+}
+
+/// How one segment is played, where it differs from the global settings.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Overrides {
+    pub speed: Option<f32>,
+    pub repeats: Option<u32>,
+    pub gain_db: Option<f32>,
+    /// Silence after each repetition.
+    pub gap: Option<Duration>,
+}
+
+impl Overrides {
+    /// Short descriptions of what's overridden, for showing next to the segment.
+    pub fn badges(&self) -> Vec<String> {
+        let mut badges = vec![];
+        if let Some(speed) = self.speed {
+            badges.push(format!("{speed:.2}x"));
+        }
+        if let Some(repeats) = self.repeats {
+            badges.push(format!("×{repeats}"));
+        }
+        if let Some(db) = self.gain_db {
+            badges.push(format!("{db:+.0} dB"));
+        }
+        if let Some(gap) = self.gap {
+            badges.push(format!("gap {:.1}s", gap.as_secs_f32()));
+        }
+        badges
+    }
+}
+
+/// `db` as a factor to multiply samples by.
+pub fn gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Marks used to be saved as bare positions; keep reading those.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum MarkRepr {
+    Pos(f32),
+    Full {
+        pos: f32,
+        #[serde(default)]
+        label: String,
+        #[serde(default)]
+        translation: String,
+        #[serde(default)]
+        overrides: Overrides,
+    },
+}
+
+impl From<MarkRepr> for Mark {
+    fn from(repr: MarkRepr) -> Self {
+        match repr {
+            MarkRepr::Pos(pos) => Mark::new(pos),
+            MarkRepr::Full {
+                pos,
+                label,
+                translation,
+                overrides,
+            } => Mark {
+                pos,
+                label,
+                translation,
+                overrides,
+            },
+        }
+    }
+}
+
+/// A stretch picked out directly by its start and end, rather than lying between two
+/// marks. These can overlap, for phrases that come round more than once.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Span {
+    pub start: f32,
+    pub end: f32,
+    pub label: String,
+}
+
+impl Span {
+    pub fn new(start: f32, end: f32) -> Self {
+        Self {
+            start,
+            end,
+            label: String::new(),
+        }
+    }
+}
+
+/// A stretch of audio between two marks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub start: Duration,
+    pub end: Duration,
+    pub label: String,
+    pub translation: String,
+    pub overrides: Overrides,
+}
+
+impl Segment {
+    pub fn new(start: Duration, end: Duration) -> Self {
+        Self {
+            start,
+            end,
+            label: String::new(),
+            translation: String::new(),
+            overrides: Default::default(),
+        }
+    }
+}
+
+/// The segment from mark `ind` up to the next mark, or the end of the file.
+pub fn segment_at(marks: &[Mark], ind: usize, total: Duration) -> Option<Segment> {
+    let mark = marks.get(ind)?;
+    let end = marks.get(ind + 1).map(|m| m.pos).unwrap_or(1.0);
+    Some(Segment {
+        start: total.mul_f32(mark.pos.clamp(0.0, 1.0)),
+        end: total.mul_f32(end.clamp(0.0, 1.0)),
+        label: mark.label.clone(),
+        translation: mark.translation.clone(),
+        overrides: mark.overrides.clone(),
+    })
+}
+
+/// Where the segment containing `pos` starts: the last mark at or before it, or the start
+/// of the file.
+pub fn segment_start(marks: &[Mark], pos: f32) -> f32 {
+    marks
+        .iter()
+        .rev()
+        .map(|m| m.pos)
+        .find(|p| *p <= pos)
+        .unwrap_or(0.0)
+}
+
+/// Back-to-back segments covering the whole of `total`, including the stretch before
+/// the first mark.
+pub fn segments(marks: &[Mark], total: Duration) -> Vec<Segment> {
+    let mut segments = vec![];
+    if marks.first().is_none_or(|m| m.pos > 0.0) {
+        let end = marks.first().map_or(1.0, |m| m.pos);
+        segments.push(Segment::new(
+            Duration::ZERO,
+            total.mul_f32(end.clamp(0.0, 1.0)),
+        ));
+    }
+    segments.extend((0..marks.len()).filter_map(|ind| segment_at(marks, ind, total)));
+    segments.retain(|s| s.end > s.start);
+    segments
+}
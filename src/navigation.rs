@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use crate::marks::Mark;
+
+/// While playing, Prev pressed this soon after a mark goes to the one before it: by the
+/// time you react to hearing a segment start, you're already a little way into it.
+pub const GRACE: Duration = Duration::from_millis(500);
+
+/// Positions come back from the player rounded to a frame, so they can land a hair
+/// before where they were sent.
+const SLACK: Duration = Duration::from_millis(5);
+
+/// Where to navigate from when the playhead is at `pos`. Within `pre_roll` before a mark
+/// counts as on it, since that's where jumping to the mark starts playback; otherwise
+/// Next would find the same mark again.
+pub fn through_preroll(marks: &[Mark], pos: f32, total: Duration, pre_roll: Duration) -> f32 {
+    if pre_roll.is_zero() {
+        return pos;
+    }
+    let pre = (pre_roll + SLACK).as_secs_f32() / total.as_secs_f32();
+    marks
+        .iter()
+        .map(|m| m.pos)
+        .find(|p| *p > pos && *p <= pos + pre)
+        .unwrap_or(pos)
+}
+
+/// Where Prev goes from `pos`: the start of the segment under the playhead, or the
+/// segment before that if we're no more than `grace` into it. Positions are fractions
+/// of the `total` length, like the marks.
+pub fn prev(marks: &[Mark], pos: f32, total: Duration, grace: Duration) -> f32 {
+    let before = |pos: f32| marks.iter().rev().map(|m| m.pos).find(|p| *p < pos);
+    let Some(start) = marks.iter().rev().map(|m| m.pos).find(|p| *p <= pos) else {
+        return 0.0;
+    };
+    if (pos - start) * total.as_secs_f32() <= grace.as_secs_f32() {
+        before(start).unwrap_or(0.0)
+    } else {
+        start
+    }
+}
+
+/// Where Next goes from `pos`: the first mark after it, or the end.
+pub fn next(marks: &[Mark], pos: f32) -> f32 {
+    marks
+        .iter()
+        .map(|m| m.pos)
+        .find(|p| *p > pos)
+        .unwrap_or(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOTAL: Duration = Duration::from_secs(100);
+
+    /// A position `secs` into [`TOTAL`].
+    fn at(secs: f32) -> f32 {
+        secs / TOTAL.as_secs_f32()
+    }
+
+    fn marks(secs: &[f32]) -> Vec<Mark> {
+        secs.iter().map(|s| Mark::new(at(*s))).collect()
+    }
+
+    #[test]
+    fn no_marks() {
+        assert_eq!(prev(&[], at(50.0), TOTAL, GRACE), 0.0);
+        assert_eq!(prev(&[], at(50.0), TOTAL, Duration::ZERO), 0.0);
+        assert_eq!(next(&[], at(50.0)), 1.0);
+    }
+
+    #[test]
+    fn before_first_mark() {
+        let marks = marks(&[10.0, 20.0]);
+        assert_eq!(prev(&marks, at(5.0), TOTAL, GRACE), 0.0);
+        assert_eq!(next(&marks, at(5.0)), at(10.0));
+    }
+
+    #[test]
+    fn after_last_mark() {
+        let marks = marks(&[10.0, 20.0]);
+        assert_eq!(prev(&marks, at(30.0), TOTAL, GRACE), at(20.0));
+        // just past it while playing: the segment before
+        assert_eq!(prev(&marks, at(20.3), TOTAL, GRACE), at(10.0));
+        assert_eq!(next(&marks, at(30.0)), 1.0);
+    }
+
+    #[test]
+    fn exactly_on_a_mark() {
+        let marks = marks(&[10.0, 20.0]);
+        assert_eq!(prev(&marks, at(20.0), TOTAL, Duration::ZERO), at(10.0));
+        assert_eq!(prev(&marks, at(10.0), TOTAL, Duration::ZERO), 0.0);
+        assert_eq!(next(&marks, at(10.0)), at(20.0));
+    }
+
+    #[test]
+    fn grace_is_in_time_not_fraction() {
+        let marks = marks(&[10.0, 20.0]);
+        assert_eq!(prev(&marks, at(20.3), TOTAL, Duration::ZERO), at(20.0));
+        assert_eq!(prev(&marks, at(20.3), TOTAL, GRACE), at(10.0));
+        assert_eq!(prev(&marks, at(21.0), TOTAL, GRACE), at(20.0));
+        // a mark a minute through an hour: 0.05 of the file would skip three of them
+        let hour = Duration::from_secs(3600);
+        let marks: Vec<_> = (1..60).map(|m| Mark::new(m as f32 / 60.0)).collect();
+        let pos = 30.0 / 60.0 + 2.0 / 3600.0;
+        assert_eq!(prev(&marks, pos, hour, GRACE), 30.0 / 60.0);
+    }
+
+    #[test]
+    fn pre_roll_counts_as_the_mark() {
+        let marks = marks(&[10.0, 20.0]);
+        let pre = Duration::from_millis(150);
+        let pos = through_preroll(&marks, at(19.85), TOTAL, pre);
+        assert_eq!(pos, at(20.0));
+        assert_eq!(next(&marks, pos), 1.0);
+        assert_eq!(through_preroll(&marks, at(19.5), TOTAL, pre), at(19.5));
+        assert_eq!(
+            through_preroll(&marks, at(19.95), TOTAL, Duration::ZERO),
+            at(19.95)
+        );
+    }
+}
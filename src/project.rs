@@ -0,0 +1,46 @@
+use anyhow::{Context, Result};
+use std::path;
+
+use crate::edit::History;
+use crate::marks::{Mark, Span};
+
+/// Everything needed to pick a lesson back up: the audio file, its marks, their undo
+/// history and the speed it was being practised at. Stored as RON, same as the eframe app state.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Project {
+    pub audio_path: Option<String>,
+    pub marks: Vec<Mark>,
+    pub spans: Vec<Span>,
+    pub history: History,
+    pub playback_speed: f32,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self {
+            audio_path: None,
+            marks: vec![],
+            spans: vec![],
+            history: Default::default(),
+            playback_speed: 1.0,
+        }
+    }
+}
+
+impl Project {
+    pub fn load<P: AsRef<path::Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("couldn't read project {}", path.display()))?;
+        ron::from_str(&contents)
+            .with_context(|| format!("couldn't parse project {}", path.display()))
+    }
+
+    pub fn save<P: AsRef<path::Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let contents = ron::ser::to_string_pretty(self, Default::default())?;
+        std::fs::write(path, contents)
+            .with_context(|| format!("couldn't write project {}", path.display()))
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use crate::app::audio::{Pcm, FADE_IN};
+use crate::app::filters::Filters;
+use crate::marks::{self, Segment};
+
+const BEEP_FREQ: f32 = 880.0;
+const BEEP_LENGTH: Duration = Duration::from_millis(150);
+const BEEP_FADE: Duration = Duration::from_millis(10);
+
+/// How a practice track is put together from the marked segments.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub speed: f32,
+    /// How many times each segment is played.
+    pub repeat: u32,
+    /// Silence after every repetition, to shadow into.
+    pub gap: Duration,
+    /// Extra silence after every repetition, as a multiple of how long it took to play,
+    /// so longer phrases leave more room to repeat them.
+    pub gap_scale: f32,
+    /// Play a short cue tone before each new segment.
+    pub beep: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            repeat: 2,
+            gap: Duration::from_millis(500),
+            gap_scale: 1.0,
+            beep: false,
+        }
+    }
+}
+
+/// Builds a practice track from `segments` of `pcm`, cleaned up by `filters` as for live
+/// playback. A segment's own overrides win over `settings`.
+pub fn practice_track(
+    pcm: &Pcm,
+    segments: &[Segment],
+    settings: &RenderSettings,
+    filters: &Filters,
+) -> Pcm {
+    let mut out = Pcm::new(pcm.channels, pcm.sample_rate);
+    let beep = beep(pcm.channels, pcm.sample_rate);
+    for segment in segments {
+        let overrides = &segment.overrides;
+        let mut clip = pcm.slice(segment.start, segment.end).processed(
+            filters,
+            overrides.speed.unwrap_or(settings.speed),
+            FADE_IN,
+        );
+        if let Some(db) = overrides.gain_db {
+            clip.amplify(marks::gain(db));
+        }
+        let gap = overrides.gap.unwrap_or(settings.gap)
+            + clip.duration().mul_f32(settings.gap_scale.max(0.0));
+        if settings.beep {
+            out.append(&beep);
+            out.append_silence(BEEP_LENGTH);
+        }
+        for _ in 0..overrides.repeats.unwrap_or(settings.repeat) {
+            out.append(&clip);
+            out.append_silence(gap);
+        }
+    }
+    out
+}
+
+/// A short sine tone, faded at both ends so it doesn't click.
+fn beep(channels: u16, sample_rate: u32) -> Pcm {
+    let len = (BEEP_LENGTH.as_secs_f32() * sample_rate as f32) as usize;
+    let fade = (BEEP_FADE.as_secs_f32() * sample_rate as f32) as usize;
+    let mut pcm = Pcm::new(channels, sample_rate);
+    for i in 0..len {
+        let t = i as f32 / sample_rate as f32;
+        let env = (i.min(len - i) as f32 / fade as f32).min(1.0);
+        let v = (t * BEEP_FREQ * std::f32::consts::TAU).sin() * env * 0.3;
+        let sample = (v * i16::MAX as f32) as i16;
+        pcm.samples
+            .extend(std::iter::repeat_n(sample, channels as usize));
+    }
+    pcm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::filters::FilterSettings;
+
+    const RATE: u32 = 16_000;
+
+    /// A second of 40Hz rumble, below the voice band.
+    fn rumble() -> Pcm {
+        let samples = (0..RATE)
+            .map(|i| {
+                ((i as f32 * 40.0 / RATE as f32 * std::f32::consts::TAU).sin() * 8000.0) as i16
+            })
+            .collect();
+        Pcm {
+            channels: 1,
+            sample_rate: RATE,
+            samples,
+        }
+    }
+
+    fn energy(pcm: &Pcm) -> f64 {
+        pcm.samples.iter().map(|s| (*s as f64).powi(2)).sum()
+    }
+
+    #[test]
+    fn renders_through_the_filters_that_are_on() {
+        let pcm = rumble();
+        let segments = [Segment::new(Duration::ZERO, Duration::from_secs(1))];
+        let settings = RenderSettings {
+            repeat: 1,
+            gap: Duration::ZERO,
+            gap_scale: 0.0,
+            ..Default::default()
+        };
+        let plain = practice_track(&pcm, &segments, &settings, &Filters::default());
+        let filters = Filters::default();
+        filters.apply(&FilterSettings {
+            voice_band: true,
+            ..Default::default()
+        });
+        let filtered = practice_track(&pcm, &segments, &settings, &filters);
+        assert_eq!(filtered.samples.len(), plain.samples.len());
+        assert!(energy(&filtered) < energy(&plain) * 0.1);
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::marks::Segment;
+
+const MIN_EASE: f32 = 1.3;
+const START_EASE: f32 = 2.5;
+
+/// Days since the unix epoch. Scheduling works in whole days so it's deterministic.
+pub type Day = i64;
+
+pub fn today() -> Day {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    (secs / 86_400) as Day
+}
+
+/// Self-assessed recall after shadowing a segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Grade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl Grade {
+    pub const ALL: [Grade; 4] = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Grade::Again => "Again",
+            Grade::Hard => "Hard",
+            Grade::Good => "Good",
+            Grade::Easy => "Easy",
+        }
+    }
+}
+
+/// SM-2 scheduling state for one segment.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Card {
+    /// Successful reviews in a row.
+    pub reps: u32,
+    pub lapses: u32,
+    pub ease: f32,
+    pub interval: u32,
+    pub due: Day,
+}
+
+impl Default for Card {
+    fn default() -> Self {
+        Self {
+            reps: 0,
+            lapses: 0,
+            ease: START_EASE,
+            interval: 0,
+            due: 0,
+        }
+    }
+}
+
+impl Card {
+    pub fn new(today: Day) -> Self {
+        Self {
+            due: today,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_due(&self, today: Day) -> bool {
+        self.due <= today
+    }
+
+    /// Schedules the next review from `today`.
+    pub fn review(&mut self, grade: Grade, today: Day) {
+        let interval = match grade {
+            Grade::Again => {
+                self.reps = 0;
+                self.lapses += 1;
+                self.ease = (self.ease - 0.2).max(MIN_EASE);
+                1
+            }
+            Grade::Hard => {
+                self.ease = (self.ease - 0.15).max(MIN_EASE);
+                (self.interval as f32 * 1.2).round().max(1.0) as u32
+            }
+            Grade::Good => match self.reps {
+                0 => 1,
+                1 => 6,
+                _ => (self.interval as f32 * self.ease).round() as u32,
+            },
+            Grade::Easy => {
+                self.ease += 0.15;
+                match self.reps {
+                    0 => 4,
+                    _ => (self.interval as f32 * self.ease * 1.3).round() as u32,
+                }
+            }
+        };
+        if grade != Grade::Again {
+            self.reps += 1;
+        }
+        // never schedule a card sooner than it was before a successful review
+        self.interval = match grade {
+            Grade::Again | Grade::Hard => interval,
+            Grade::Good | Grade::Easy => interval.max(self.interval + 1),
+        };
+        self.due = today + self.interval as Day;
+    }
+}
+
+/// A segment of some file that is being learned.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ReviewItem {
+    pub audio_path: String,
+    pub start: Duration,
+    pub end: Duration,
+    pub label: String,
+    pub translation: String,
+    pub card: Card,
+    /// Every grade given, oldest first.
+    #[serde(default)]
+    pub history: Vec<(Day, Grade)>,
+}
+
+/// Every segment being learned, across all files. Keyed by file and start time, so
+/// re-adding the same segment keeps its history.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ReviewStore {
+    pub items: BTreeMap<String, ReviewItem>,
+}
+
+impl ReviewStore {
+    fn key(audio_path: &str, start: Duration) -> String {
+        format!("{audio_path}#{}", start.as_millis())
+    }
+
+    /// Starts learning `segment`. Returns `false` if it was already being learned.
+    pub fn add(&mut self, audio_path: &str, segment: &Segment, today: Day) -> bool {
+        let key = Self::key(audio_path, segment.start);
+        if self.items.contains_key(&key) {
+            return false;
+        }
+        self.items.insert(
+            key,
+            ReviewItem {
+                audio_path: audio_path.to_owned(),
+                start: segment.start,
+                end: segment.end,
+                label: segment.label.clone(),
+                translation: segment.translation.clone(),
+                card: Card::new(today),
+                history: vec![],
+            },
+        );
+        true
+    }
+
+    /// Keys of the items due by `today`, most overdue first.
+    pub fn due(&self, today: Day) -> Vec<String> {
+        let mut due: Vec<(&String, &ReviewItem)> = self
+            .items
+            .iter()
+            .filter(|(_, item)| item.card.is_due(today))
+            .collect();
+        due.sort_by_key(|(_, item)| item.card.due);
+        due.into_iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn grade(&mut self, key: &str, grade: Grade, today: Day) {
+        if let Some(item) = self.items.get_mut(key) {
+            item.card.review(grade, today);
+            item.history.push((today, grade));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reviewed(grades: &[Grade]) -> Card {
+        let mut card = Card::new(0);
+        let mut day = 0;
+        for grade in grades {
+            day = card.due;
+            card.review(*grade, day);
+        }
+        assert!(card.due >= day);
+        card
+    }
+
+    #[test]
+    fn new_card_is_due_immediately() {
+        let card = Card::new(100);
+        assert!(card.is_due(100));
+        assert!(!card.is_due(99));
+    }
+
+    #[test]
+    fn good_follows_sm2_intervals() {
+        let card = reviewed(&[Grade::Good]);
+        assert_eq!(card.interval, 1);
+        let card = reviewed(&[Grade::Good, Grade::Good]);
+        assert_eq!(card.interval, 6);
+        let card = reviewed(&[Grade::Good, Grade::Good, Grade::Good]);
+        assert_eq!(card.interval, 15);
+        assert_eq!(card.due, 1 + 6 + 15);
+    }
+
+    #[test]
+    fn again_resets_and_lowers_ease() {
+        let card = reviewed(&[Grade::Good, Grade::Good, Grade::Again]);
+        assert_eq!(card.reps, 0);
+        assert_eq!(card.lapses, 1);
+        assert_eq!(card.interval, 1);
+        assert!((card.ease - 2.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ease_never_drops_below_minimum() {
+        let card = reviewed(&[Grade::Again; 20]);
+        assert!((card.ease - MIN_EASE).abs() < 1e-6);
+    }
+
+    #[test]
+    fn easy_grows_faster_than_good() {
+        let good = reviewed(&[Grade::Good, Grade::Good, Grade::Good]);
+        let easy = reviewed(&[Grade::Easy, Grade::Easy, Grade::Easy]);
+        assert_eq!(easy.interval, 58);
+        assert!(easy.interval > good.interval);
+        assert!(easy.ease > good.ease);
+    }
+
+    #[test]
+    fn hard_grows_slowly() {
+        let card = reviewed(&[Grade::Good, Grade::Good, Grade::Hard]);
+        assert_eq!(card.interval, 7);
+        assert!((card.ease - 2.35).abs() < 1e-6);
+    }
+
+    #[test]
+    fn store_keeps_history_and_orders_due() {
+        let mut store = ReviewStore::default();
+        let a = Segment::new(Duration::from_secs(1), Duration::from_secs(2));
+        let b = Segment::new(Duration::from_secs(3), Duration::from_secs(4));
+        assert!(store.add("lesson.mp3", &a, 10));
+        assert!(store.add("lesson.mp3", &b, 5));
+        assert!(!store.add("lesson.mp3", &a, 10));
+
+        let due = store.due(10);
+        assert_eq!(due, vec!["lesson.mp3#3000", "lesson.mp3#1000"]);
+
+        store.grade(&due[0], Grade::Good, 10);
+        assert_eq!(store.due(10), vec!["lesson.mp3#1000"]);
+        assert_eq!(store.items[&due[0]].history, vec![(10, Grade::Good)]);
+        assert_eq!(store.due(11).len(), 2);
+    }
+}
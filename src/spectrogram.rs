@@ -0,0 +1,69 @@
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::analysis;
+
+/// Time between columns.
+pub const HOP: Duration = Duration::from_millis(10);
+/// Bins per column; the FFT is twice this long.
+pub const ROWS: usize = 256;
+/// Levels at or below this many dB under full scale come out as 0.
+const FLOOR_DB: f32 = -90.0;
+
+/// Short-time Fourier transform of a whole recording, a column at a time so it can be
+/// worked through in pieces.
+pub struct Stft {
+    samples: Vec<f32>,
+    hop: usize,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+}
+
+impl Stft {
+    /// From mono `samples`. Everything above 8kHz is dropped, speech doesn't need it.
+    pub fn new(samples: &[f32], sample_rate: u32) -> Self {
+        let (samples, rate) = analysis::downsample(samples, sample_rate, analysis::ANALYSIS_RATE);
+        let len = 2 * ROWS;
+        Self {
+            samples,
+            hop: ((HOP.as_secs_f64() * rate as f64).round() as usize).max(1),
+            fft: FftPlanner::new().plan_fft_forward(len),
+            window: (0..len)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos())
+                .collect(),
+            buffer: vec![Complex::new(0.0, 0.0); len],
+        }
+    }
+
+    /// Number of columns.
+    pub fn len(&self) -> usize {
+        self.samples.len().div_ceil(self.hop)
+    }
+
+    /// Levels of column `ind`, lowest frequency first, from 0 at [`FLOOR_DB`] to 255 at
+    /// full scale.
+    pub fn column(&mut self, ind: usize) -> [u8; ROWS] {
+        let len = self.window.len();
+        let start = (ind * self.hop) as isize - len as isize / 2;
+        for (i, (b, w)) in self.buffer.iter_mut().zip(&self.window).enumerate() {
+            let s = usize::try_from(start + i as isize)
+                .ok()
+                .and_then(|s| self.samples.get(s));
+            *b = Complex::new(s.copied().unwrap_or(0.0) * w, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        // a full scale sine peaks at a quarter of the length with a Hann window
+        let full_scale = len as f32 / 4.0;
+        let mut column = [0; ROWS];
+        for (level, c) in column.iter_mut().zip(&self.buffer) {
+            let db = 20.0 * (c.norm() / full_scale + 1e-9).log10();
+            *level = ((1.0 - db / FLOOR_DB).clamp(0.0, 1.0) * 255.0) as u8;
+        }
+        column
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{bail, Context, Result};
+use std::fmt::Write;
+use std::path;
+use std::time::Duration;
+
+/// A single SubRip cue. Marks are stored as the cue start times.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+impl Cue {
+    /// The first line of the text is taken as the transcript and the rest as its translation.
+    pub fn transcript(&self) -> (String, String) {
+        let mut lines = self.text.lines();
+        let transcript = lines.next().unwrap_or_default().to_owned();
+        (transcript, lines.collect::<Vec<_>>().join(" "))
+    }
+}
+
+pub fn load<P: AsRef<path::Path>>(path: P) -> Result<Vec<Cue>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("couldn't read marks file {}", path.display()))?;
+    parse(&contents).with_context(|| format!("couldn't parse marks file {}", path.display()))
+}
+
+pub fn save<P: AsRef<path::Path>>(path: P, cues: &[Cue]) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::write(path, write(cues))
+        .with_context(|| format!("couldn't write marks file {}", path.display()))
+}
+
+pub fn parse(contents: &str) -> Result<Vec<Cue>> {
+    let contents = contents
+        .trim_start_matches('\u{feff}')
+        .replace("\r\n", "\n");
+    let mut cues = vec![];
+    for block in contents.split("\n\n") {
+        let mut lines = block.lines().map(str::trim).skip_while(|l| l.is_empty());
+        let Some(first) = lines.next() else {
+            continue;
+        };
+        // the numeric counter is optional in the wild, so accept the timing line first too
+        let timing = if first.contains("-->") {
+            first
+        } else {
+            match lines.next() {
+                Some(l) => l,
+                None => bail!("cue {first} has no timing line"),
+            }
+        };
+        let Some((start, end)) = timing.split_once("-->") else {
+            bail!("expected `start --> end`, got `{timing}`");
+        };
+        let start = parse_timestamp(start.trim())?;
+        // ignore any position hints after the end time
+        let end = parse_timestamp(end.split_whitespace().next().unwrap_or_default())?;
+        let text = lines.collect::<Vec<_>>().join("\n");
+        cues.push(Cue { start, end, text });
+    }
+    cues.sort_by_key(|c| c.start);
+    Ok(cues)
+}
+
+pub fn write(cues: &[Cue]) -> String {
+    let mut out = String::new();
+    for (ind, cue) in cues.iter().enumerate() {
+        _ = writeln!(
+            out,
+            "{}\n{} --> {}\n{}\n",
+            ind + 1,
+            format_timestamp(cue.start),
+            format_timestamp(cue.end),
+            cue.text
+        );
+    }
+    out
+}
+
+/// Parses `HH:MM:SS,mmm` (SubRip) timestamps. A `.` is accepted in place of the comma.
+pub fn parse_timestamp(s: &str) -> Result<Duration> {
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() != 3 {
+        bail!("invalid timestamp `{s}`, expected HH:MM:SS,mmm");
+    }
+    let hours: u64 = parts[0]
+        .parse()
+        .with_context(|| format!("invalid hours in `{s}`"))?;
+    let minutes: u64 = parts[1]
+        .parse()
+        .with_context(|| format!("invalid minutes in `{s}`"))?;
+    let (secs, millis) = parts[2].split_once([',', '.']).unwrap_or((parts[2], "0"));
+    let secs: u64 = secs
+        .parse()
+        .with_context(|| format!("invalid seconds in `{s}`"))?;
+    let millis: u64 = format!("{millis:0<3}")
+        .chars()
+        .take(3)
+        .collect::<String>()
+        .parse()
+        .with_context(|| format!("invalid milliseconds in `{s}`"))?;
+    Ok(Duration::from_millis(
+        ((hours * 60 + minutes) * 60 + secs) * 1000 + millis,
+    ))
+}
+
+pub fn format_timestamp(t: Duration) -> String {
+    let millis = t.as_millis();
+    format!(
+        "{:0>2}:{:0>2}:{:0>2},{:0>3}",
+        millis / 3_600_000,
+        (millis / 60_000) % 60,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        let ms = Duration::from_millis;
+        assert_eq!(parse_timestamp("00:01:02,345").unwrap(), ms(62_345));
+        assert_eq!(parse_timestamp("01:00:00.5").unwrap(), ms(3_600_500));
+        assert_eq!(parse_timestamp("00:00:07").unwrap(), ms(7_000));
+        for bad in ["1:02", "00:xx:00,000", "00:00:00,abc", ""] {
+            assert!(parse_timestamp(bad).is_err(), "{bad} was accepted");
+        }
+        assert_eq!(format_timestamp(ms(3_723_004)), "01:02:03,004");
+    }
+
+    #[test]
+    fn parses_cues_from_the_wild() {
+        let contents = "\u{feff}2\r\n00:00:05,000 --> 00:00:06,000 X1:0\r\nlater\r\n\r\n\
+                        00:00:01,000 --> 00:00:02,500\nhola\nhello\n\n\n";
+        let cues = parse(contents).unwrap();
+        assert_eq!(cues.len(), 2);
+        assert_eq!(cues[0].start, Duration::from_secs(1));
+        assert_eq!(cues[0].end, Duration::from_millis(2500));
+        assert_eq!(cues[0].transcript(), ("hola".into(), "hello".into()));
+        assert_eq!(cues[1].text, "later");
+        assert!(parse("1\nno timing here\n").is_err());
+        assert!(parse("1\n").is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        let cues = vec![
+            Cue {
+                start: Duration::from_millis(1_250),
+                end: Duration::from_millis(2_000),
+                text: "uno\none".into(),
+            },
+            Cue {
+                start: Duration::from_millis(3_723_004),
+                end: Duration::from_millis(3_725_000),
+                text: String::new(),
+            },
+        ];
+        assert_eq!(parse(&write(&cues)).unwrap(), cues);
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::review::{self, Day};
+
+/// Something that happened while practising.
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    /// Playback started, or moved on into a new segment.
+    Play,
+    /// Playback went back over the segment it was already in.
+    Loop,
+    Listen(Duration),
+    /// Silence left after a segment for shadowing it.
+    Gap(Duration),
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Counters {
+    pub plays: u32,
+    pub loops: u32,
+    pub listening: Duration,
+    pub gaps: Duration,
+}
+
+impl Counters {
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::Play => self.plays += 1,
+            Event::Loop => self.loops += 1,
+            Event::Listen(dur) => self.listening += dur,
+            Event::Gap(dur) => self.gaps += dur,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plays == 0 && self.listening.is_zero() && self.gaps.is_zero()
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FileStats {
+    pub total: Counters,
+    /// Keyed by the segment's start in milliseconds.
+    pub segments: BTreeMap<u64, Counters>,
+}
+
+/// Practice counters per file, per segment and per day.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct PracticeStats {
+    pub files: BTreeMap<String, FileStats>,
+    pub days: BTreeMap<Day, Counters>,
+}
+
+impl PracticeStats {
+    /// Counts `event` for the day, the file and, if it's known, the segment starting at `segment`.
+    pub fn record(&mut self, file: &str, segment: Option<Duration>, event: Event, today: Day) {
+        self.days.entry(today).or_default().apply(event);
+        let stats = self.files.entry(file.to_owned()).or_default();
+        stats.total.apply(event);
+        if let Some(start) = segment {
+            stats
+                .segments
+                .entry(start.as_millis() as u64)
+                .or_default()
+                .apply(event);
+        }
+    }
+
+    /// Like [`PracticeStats::record`], but only for the segment, not the day or file.
+    pub fn record_segment(&mut self, file: &str, segment: Duration, event: Event) {
+        self.files
+            .entry(file.to_owned())
+            .or_default()
+            .segments
+            .entry(segment.as_millis() as u64)
+            .or_default()
+            .apply(event);
+    }
+
+    pub fn total(&self) -> Counters {
+        let mut total = Counters::default();
+        for day in self.days.values() {
+            total.plays += day.plays;
+            total.loops += day.loops;
+            total.listening += day.listening;
+            total.gaps += day.gaps;
+        }
+        total
+    }
+
+    /// Days in a row practised, ending today. Today doesn't break the streak until it's over.
+    pub fn current_streak(&self, today: Day) -> u32 {
+        let practised = |day: Day| self.days.get(&day).is_some_and(|c| !c.is_empty());
+        let mut day = if practised(today) { today } else { today - 1 };
+        let mut streak = 0;
+        while practised(day) {
+            streak += 1;
+            day -= 1;
+        }
+        streak
+    }
+
+    pub fn longest_streak(&self) -> u32 {
+        let (mut longest, mut streak, mut last) = (0, 0, None);
+        for (day, _) in self.days.iter().filter(|(_, c)| !c.is_empty()) {
+            streak = if last == Some(day - 1) { streak + 1 } else { 1 };
+            longest = longest.max(streak);
+            last = Some(*day);
+        }
+        longest
+    }
+
+    /// One row per day practised.
+    pub fn daily_csv(&self) -> String {
+        let mut csv = "date,plays,loops,listening_secs,gap_secs\n".to_owned();
+        for (day, c) in self.days.iter() {
+            _ = writeln!(
+                csv,
+                "{},{},{},{:.1},{:.1}",
+                date(*day),
+                c.plays,
+                c.loops,
+                c.listening.as_secs_f32(),
+                c.gaps.as_secs_f32()
+            );
+        }
+        csv
+    }
+
+    /// One row per segment practised, across all files.
+    pub fn segments_csv(&self) -> String {
+        let mut csv = "file,segment_start_secs,plays,loops,listening_secs,gap_secs\n".to_owned();
+        for (file, stats) in self.files.iter() {
+            for (start, c) in stats.segments.iter() {
+                _ = writeln!(
+                    csv,
+                    "{},{:.3},{},{},{:.1},{:.1}",
+                    csv_field(file),
+                    *start as f64 / 1000.0,
+                    c.plays,
+                    c.loops,
+                    c.listening.as_secs_f32(),
+                    c.gaps.as_secs_f32()
+                );
+            }
+        }
+        csv
+    }
+}
+
+/// `YYYY-MM-DD` for a day number.
+pub fn date(day: Day) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = day + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// The player's state over one frame, for [`Tracker::tick`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Playhead {
+    pub playing: bool,
+    /// The start of the segment the playhead is in.
+    pub segment: Duration,
+    pub pos: Duration,
+    /// How much gap the player has played in all, the time left to shadow in.
+    pub silence: Duration,
+}
+
+/// Turns the player's state each frame into [`Event`]s.
+#[derive(Default)]
+pub struct Tracker {
+    last: Playhead,
+}
+
+impl Tracker {
+    pub fn tick(&mut self, stats: &mut PracticeStats, file: &str, now: Playhead, dt: Duration) {
+        let today = review::today();
+        let (last, segment) = (self.last, Some(now.segment));
+        // it starts again from nothing with each file
+        let gap = now.silence.saturating_sub(last.silence);
+        if now.playing {
+            if !last.playing {
+                stats.record(file, segment, Event::Play, today);
+            } else if now.segment != last.segment {
+                stats.record_segment(file, now.segment, Event::Play);
+            } else if now.pos + Duration::from_millis(500) < last.pos {
+                stats.record(file, segment, Event::Loop, today);
+            }
+            if !gap.is_zero() {
+                stats.record(file, segment, Event::Gap(gap), today);
+            }
+            let listened = dt.saturating_sub(gap);
+            stats.record(file, segment, Event::Listen(listened), today);
+        }
+        self.last = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_from_day_numbers() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(59), "1970-03-01");
+        assert_eq!(date(11_016), "2000-02-29");
+        assert_eq!(date(20_744), "2026-10-18");
+        assert_eq!(date(-1), "1969-12-31");
+    }
+
+    #[test]
+    fn streaks() {
+        let mut stats = PracticeStats::default();
+        for day in [10, 11, 12, 20, 21] {
+            stats.record("a.mp3", None, Event::Play, day);
+        }
+        // an empty day doesn't count
+        stats.days.insert(22, Counters::default());
+        assert_eq!(stats.longest_streak(), 3);
+        assert_eq!(stats.current_streak(21), 2);
+        // today isn't over yet
+        assert_eq!(stats.current_streak(22), 2);
+        assert_eq!(stats.current_streak(23), 0);
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("plain.mp3"), "plain.mp3");
+        assert_eq!(csv_field("a, b.mp3"), "\"a, b.mp3\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        let mut stats = PracticeStats::default();
+        stats.record("x,y.mp3", Some(Duration::from_millis(1500)), Event::Loop, 0);
+        assert_eq!(
+            stats.segments_csv().lines().nth(1),
+            Some("\"x,y.mp3\",1.500,0,1,0.0,0.0")
+        );
+    }
+
+    #[test]
+    fn gaps_are_counted_apart_from_listening() {
+        let mut stats = PracticeStats::default();
+        let mut tracker = Tracker::default();
+        let secs = Duration::from_secs;
+        let mut now = Playhead {
+            playing: true,
+            ..Default::default()
+        };
+        tracker.tick(&mut stats, "a.mp3", now, secs(1));
+        now.silence = secs(2);
+        tracker.tick(&mut stats, "a.mp3", now, secs(3));
+        let total = &stats.files["a.mp3"].total;
+        assert_eq!(total.plays, 1);
+        assert_eq!(total.gaps, secs(2));
+        assert_eq!(total.listening, secs(2));
+    }
+}